@@ -0,0 +1,705 @@
+use std::fs;
+use std::fmt;
+use std::error;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use crate::parser;
+use crate::instructions::{Instruction, Register};
+
+// programs are assembled to run from here, as `VM::load` expects
+pub const ORIGIN: u16 = 0x200;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_EXPR_DEPTH: usize = 64;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Error {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// assembles source text, resolving includes relative to
+// the current directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, Error> {
+    let mut lines = Vec::new();
+    preprocess(source, None, Path::new("."), 0, &mut lines)?;
+    Assembler::new(lines).run()
+}
+
+// assembles a file, resolving includes relative to it
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| Error {
+        file: Some(path.to_path_buf()),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut lines = Vec::new();
+    preprocess(&source, Some(path), dir, 0, &mut lines)?;
+    Assembler::new(lines).run()
+}
+
+// parses a single line of assembly, without labels or symbols; data
+// words are decoded, so anything `Display` prints parses back the same
+impl FromStr for Instruction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Instruction, Error> {
+        let line = Line {
+            file: None,
+            number: 1,
+            text: strip_comment(s).trim().to_string(),
+        };
+        let asm = Assembler::new(Vec::new());
+        let (head, rest) = split_word(&line.text);
+        let args = split_operands(rest);
+
+        match args.as_slice() {
+            [w] if head.eq_ignore_ascii_case("dw") => {
+                let w = asm.ranged(&line, w, 0xffff)? as u16;
+                Ok(parser::read(&w.to_be_bytes()))
+            },
+            _ => asm.instruction(&line, &head.to_ascii_uppercase(), &args),
+        }
+    }
+}
+
+struct Line {
+    file: Option<PathBuf>,
+    number: usize,
+    text: String,
+}
+
+impl Line {
+    fn error<S: Into<String>>(&self, message: S) -> Error {
+        Error {
+            file: self.file.clone(),
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+// splices included files in, and drops comments
+fn preprocess(
+    source: &str,
+    file: Option<&Path>,
+    dir: &Path,
+    depth: usize,
+    lines: &mut Vec<Line>,
+) -> Result<(), Error> {
+    for (k, text) in source.lines().enumerate() {
+        let line = Line {
+            file: file.map(Path::to_path_buf),
+            number: k + 1,
+            text: strip_comment(text).trim().to_string(),
+        };
+
+        let (head, rest) = split_word(&line.text);
+        if !head.eq_ignore_ascii_case("include") {
+            lines.push(line);
+            continue
+        }
+
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(line.error("includes nested too deeply"))
+        }
+        let name = parse_string(rest.trim())
+            .ok_or_else(|| line.error("expected a quoted file name"))?;
+        let path = dir.join(name);
+        let source = fs::read_to_string(&path)
+            .map_err(|e| line.error(format!("{}: {}", path.display(), e)))?;
+        let dir = path.parent().unwrap_or(dir).to_path_buf();
+        preprocess(&source, Some(&path), &dir, depth + 1, lines)?;
+    }
+    Ok(())
+}
+
+// the characters of `text` outside strings and character literals,
+// so that `;` and `,` in them aren't taken for comments and operands
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quoted = false;
+    let mut skip = 0;
+    text.char_indices().filter(move |&(i, c)| {
+        if skip > 0 {
+            skip -= 1;
+            return false
+        }
+        match c {
+            '"' => {
+                quoted = !quoted;
+                false
+            },
+            '\'' if !quoted && text[i..].chars().nth(2) == Some('\'') => {
+                skip = 2;
+                false
+            },
+            _ => !quoted,
+        }
+    })
+}
+
+fn strip_comment(text: &str) -> &str {
+    match unquoted(text).find(|&(_, c)| c == ';') {
+        Some((i, _)) => &text[..i],
+        None => text,
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    }
+}
+
+fn parse_string(text: &str) -> Option<&str> {
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Some(&text[1..text.len()-1])
+    } else {
+        None
+    }
+}
+
+enum Statement {
+    Instruction(String, Vec<String>),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+enum Symbol {
+    Address(u16),
+    Constant(String, usize),
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Assembler {
+    fn new(lines: Vec<Line>) -> Assembler {
+        Assembler { lines, symbols: HashMap::new() }
+    }
+
+    fn run(mut self) -> Result<Vec<u8>, Error> {
+        // first pass: find out where everything goes
+        let mut statements = Vec::new();
+        let mut addr = ORIGIN as u32;
+
+        for k in 0..self.lines.len() {
+            let line = &self.lines[k];
+            let mut text = line.text.as_str();
+
+            if let Some((name, expr)) = split_constant(text) {
+                if !is_identifier(name) {
+                    return Err(line.error(format!("invalid constant name `{}`", name)))
+                }
+                let sym = Symbol::Constant(expr.to_string(), k);
+                if self.symbols.insert(name.to_string(), sym).is_some() {
+                    return Err(line.error(format!("`{}` is already defined", name)))
+                }
+                continue
+            }
+
+            if let Some(i) = label_end(text) {
+                let name = &text[..i];
+                if self.symbols.insert(name.to_string(), Symbol::Address(addr as u16)).is_some() {
+                    return Err(line.error(format!("`{}` is already defined", name)))
+                }
+                text = text[i+1..].trim();
+            }
+            if text.is_empty() {
+                continue
+            }
+
+            let (head, rest) = split_word(text);
+            let args = split_operands(rest);
+            let stmt = match head.to_ascii_lowercase().as_str() {
+                "db" => {
+                    addr += args.iter().map(|a| data_len(a)).sum::<usize>() as u32;
+                    Statement::Bytes(args)
+                },
+                "dw" => {
+                    addr += 2 * args.len() as u32;
+                    Statement::Words(args)
+                },
+                _ => {
+                    addr += instruction_size(head, &args);
+                    Statement::Instruction(head.to_ascii_uppercase(), args)
+                },
+            };
+            if addr > 0x10000 {
+                return Err(line.error("program does not fit in memory"))
+            }
+            statements.push((k, stmt));
+        }
+
+        // second pass: evaluate operands and emit code
+        let mut out = Vec::new();
+
+        for (k, stmt) in statements.iter() {
+            let line = &self.lines[*k];
+            match stmt {
+                Statement::Bytes(args) => for arg in args {
+                    match parse_string(arg) {
+                        Some(s) => out.extend_from_slice(s.as_bytes()),
+                        None => out.push(self.byte(line, arg)?),
+                    }
+                },
+                Statement::Words(args) => for arg in args {
+                    let w = self.ranged(line, arg, 0xffff)? as u16;
+                    out.extend_from_slice(&w.to_be_bytes());
+                },
+                Statement::Instruction(mnemonic, args) => {
+                    let inst = self.instruction(line, mnemonic, args)?;
+                    out.extend_from_slice(&inst.encode().to_be_bytes());
+                    if let Instruction::LDLONG(addr) = inst {
+                        out.extend_from_slice(&addr.to_be_bytes());
+                    }
+                },
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn instruction(&self, line: &Line, mnemonic: &str, args: &[String]) -> Result<Instruction, Error> {
+        use Instruction::*;
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let bad = || line.error(format!("invalid operands for {}: {}", mnemonic, args.join(", ")));
+
+        let inst = match (mnemonic, args.as_slice()) {
+            ("CLS", []) => CLS,
+            ("RET", []) => RET,
+            ("SCR", []) => SCR,
+            ("SCL", []) => SCL,
+            ("EXIT", []) => EXIT,
+            ("LOW", []) => LOW,
+            ("HIGH", []) => HIGH,
+            ("AUDIO", []) => AUDIO,
+            ("JP", [v, a]) if register(v) == Some(0) => JPAFAR(self.address(line, a)?),
+            ("JP", [a]) => JPA(self.address(line, a)?),
+            ("CALL", [a]) => CALL(self.address(line, a)?),
+            ("SE", [x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => SER(x, y),
+                (Some(x), None) => SEI(x, self.byte(line, y)?),
+                _ => return Err(bad()),
+            },
+            ("SNE", [x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => SNER(x, y),
+                (Some(x), None) => SNEI(x, self.byte(line, y)?),
+                _ => return Err(bad()),
+            },
+            ("ADD", [i, x]) if i.eq_ignore_ascii_case("I") => {
+                ADDA(register(x).ok_or_else(bad)?)
+            },
+            ("ADD", [x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => ADDR(x, y),
+                (Some(x), None) => ADDI(x, self.byte(line, y)?),
+                _ => return Err(bad()),
+            },
+            ("OR", [x, y]) => ORR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("AND", [x, y]) => ANDR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("XOR", [x, y]) => XORR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("SUB", [x, y]) => SUBR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("SUBN", [x, y]) => SUBNR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("SHR", [x]) => SHRR(register(x).ok_or_else(bad)?, 0),
+            ("SHR", [x, y]) => SHRR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("SHL", [x]) => SHLR(register(x).ok_or_else(bad)?, 0),
+            ("SHL", [x, y]) => SHLR(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("RND", [x, b]) => RND(register(x).ok_or_else(bad)?, self.byte(line, b)?),
+            ("DRW", [x, y, n]) => DRW(
+                register(x).ok_or_else(bad)?,
+                register(y).ok_or_else(bad)?,
+                self.ranged(line, n, 0xf)? as u8,
+            ),
+            ("SKP", [x]) => SKP(register(x).ok_or_else(bad)?),
+            ("SKNP", [x]) => SKNP(register(x).ok_or_else(bad)?),
+            ("SCD", [n]) => SCD(self.ranged(line, n, 0xf)? as u8),
+            ("SCU", [n]) => SCU(self.ranged(line, n, 0xf)? as u8),
+            ("PLANE", [n]) => PLANE(self.ranged(line, n, 0xf)? as u8),
+            ("PITCH", [x]) => PITCH(register(x).ok_or_else(bad)?),
+            ("SAVE", [x, y]) => LDRNGST(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("LOAD", [x, y]) => LDRNGRD(register(x).ok_or_else(bad)?, register(y).ok_or_else(bad)?),
+            ("LD", [dst, src]) => self.load(line, dst, src).ok_or_else(bad)??,
+            _ => return Err(line.error(format!("unknown instruction `{} {}`", mnemonic, args.join(", ")))),
+        };
+
+        Ok(inst)
+    }
+
+    // the many forms of LD, or None if the operands don't fit any of them
+    fn load(&self, line: &Line, dst: &str, src: &str) -> Option<Result<Instruction, Error>> {
+        use Instruction::*;
+
+        let upper = |s: &str| s.to_ascii_uppercase();
+        let inst = match (register(dst), register(src)) {
+            (Some(x), Some(y)) => LDR(x, y),
+            (Some(x), None) => match upper(src).as_str() {
+                "DT" => LDTG(x),
+                "K" => LDK(x),
+                "[I]" => LDREGRD(x),
+                "R" => LDRPLRD(x),
+                _ => return Some(self.byte(line, src).map(|b| LDI(x, b))),
+            },
+            (None, Some(x)) => match upper(dst).as_str() {
+                "DT" => LDTS(x),
+                "ST" => LDSS(x),
+                "F" => LDDIG(x),
+                "HF" => LDHDIG(x),
+                "B" => LDBCD(x),
+                "[I]" => LDREGST(x),
+                "R" => LDRPLST(x),
+                _ => return None,
+            },
+            (None, None) if upper(dst) == "I" => {
+                let (head, rest) = split_word(src);
+                if head.eq_ignore_ascii_case("LONG") {
+                    return Some(self.ranged(line, rest.trim(), 0xffff).map(|a| LDLONG(a as u16)))
+                }
+                return Some(self.address(line, src).map(LDA))
+            },
+            (None, None) => return None,
+        };
+        Some(Ok(inst))
+    }
+
+    fn address(&self, line: &Line, expr: &str) -> Result<u16, Error> {
+        self.ranged(line, expr, 0xfff).map(|v| v as u16)
+    }
+
+    // bytes may also be given as negative numbers
+    fn byte(&self, line: &Line, expr: &str) -> Result<u8, Error> {
+        let v = self.eval(line, expr)?;
+        if (-128..=255).contains(&v) {
+            Ok(v as u8)
+        } else {
+            Err(line.error(format!("value {} does not fit in a byte", v)))
+        }
+    }
+
+    fn ranged(&self, line: &Line, expr: &str, max: i64) -> Result<i64, Error> {
+        let v = self.eval(line, expr)?;
+        if (0..=max).contains(&v) {
+            Ok(v)
+        } else {
+            Err(line.error(format!("value {} out of range 0..={}", v, max)))
+        }
+    }
+
+    fn eval(&self, line: &Line, expr: &str) -> Result<i64, Error> {
+        self.eval_depth(line, expr, 0)
+    }
+
+    fn eval_depth(&self, line: &Line, expr: &str, depth: usize) -> Result<i64, Error> {
+        if depth > MAX_EXPR_DEPTH {
+            return Err(line.error("constants are defined in terms of themselves"))
+        }
+        let tokens = tokenize(expr).map_err(|e| line.error(e))?;
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            pos: 0,
+            lookup: &|name: &str| match self.symbols.get(name) {
+                Some(Symbol::Address(a)) => Ok(*a as i64),
+                Some(Symbol::Constant(expr, k)) => {
+                    self.eval_depth(&self.lines[*k], expr, depth + 1)
+                },
+                None => Err(line.error(format!("undefined symbol `{}`", name))),
+            },
+        };
+        let v = parser.expr(line)?;
+        if parser.pos != tokens.len() {
+            return Err(line.error(format!("unexpected input in `{}`", expr)))
+        }
+        Ok(v)
+    }
+}
+
+fn split_constant(text: &str) -> Option<(&str, &str)> {
+    let (name, rest) = split_word(text);
+    let (op, expr) = split_word(rest.trim_start());
+    if op.eq_ignore_ascii_case("equ") {
+        Some((name, expr.trim()))
+    } else {
+        None
+    }
+}
+
+fn label_end(text: &str) -> Option<usize> {
+    let i = text.find(':')?;
+    if is_identifier(&text[..i]) {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut start = 0;
+
+    for (i, _) in unquoted(text).filter(|&(_, c)| c == ',') {
+        args.push(text[start..i].trim().to_string());
+        start = i + 1;
+    }
+    if !text[start..].trim().is_empty() || !args.is_empty() {
+        args.push(text[start..].trim().to_string());
+    }
+
+    args
+}
+
+fn data_len(arg: &str) -> usize {
+    parse_string(arg).map(str::len).unwrap_or(1)
+}
+
+fn instruction_size(mnemonic: &str, args: &[String]) -> u32 {
+    let long = mnemonic.eq_ignore_ascii_case("LD")
+        && args.len() == 2
+        && args[0].eq_ignore_ascii_case("I")
+        && split_word(&args[1]).0.eq_ignore_ascii_case("LONG");
+    if long { 4 } else { 2 }
+}
+
+fn register(s: &str) -> Option<Register> {
+    let s = s.as_bytes();
+    if s.len() != 2 || !(s[0] == b'V' || s[0] == b'v') {
+        return None
+    }
+    (s[1] as char).to_digit(16).map(|r| r as Register)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+static OPERATORS: [&str; 11] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~"];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if c == '\'' {
+            let mut chars = rest[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), Some('\'')) if ch.is_ascii() => tokens.push(Token::Number(ch as i64)),
+                _ => return Err(format!("bad character literal in `{}`", expr)),
+            }
+            rest = &rest[3..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    rest = &rest[op.len()..];
+                },
+                None => return Err(format!("unexpected `{}` in `{}`", c, expr)),
+            }
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Result<i64, String> {
+    let s = s.replace('_', "");
+    let lower = s.to_ascii_lowercase();
+    let r = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+    r.map_err(|_| format!("invalid number `{}`", s))
+}
+
+type Lookup<'a> = dyn Fn(&str) -> Result<i64, Error> + 'a;
+
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: &'a Lookup<'a>,
+}
+
+// binary operators from loosest to tightest binding
+static PRECEDENCE: [&[&str]; 5] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>", "+", "-"],
+    &["*", "/", "%"],
+];
+
+impl<'a> ExprParser<'a> {
+    fn expr(&mut self, line: &Line) -> Result<i64, Error> {
+        self.binary(line, 0)
+    }
+
+    fn binary(&mut self, line: &Line, level: usize) -> Result<i64, Error> {
+        if level == PRECEDENCE.len() {
+            return self.unary(line)
+        }
+
+        let mut lhs = self.binary(line, level + 1)?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            if !PRECEDENCE[level].contains(op) {
+                break
+            }
+            self.pos += 1;
+            let rhs = self.binary(line, level + 1)?;
+            lhs = match *op {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                ">>" => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err(line.error("division by zero")),
+                "/" => lhs / rhs,
+                _ => lhs % rhs,
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self, line: &Line) -> Result<i64, Error> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match tok {
+            Some(Token::Op("-")) => Ok(self.unary(line)?.wrapping_neg()),
+            Some(Token::Op("+")) => self.unary(line),
+            Some(Token::Op("~")) => Ok(!self.unary(line)?),
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::Ident(name)) => (self.lookup)(&name),
+            Some(Token::Open) => {
+                let v = self.expr(line)?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(v)
+                    },
+                    _ => Err(line.error("missing `)`")),
+                }
+            },
+            _ => Err(line.error("expected a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+
+    #[test]
+    fn disassembly_round_trips() {
+        let rom = include_bytes!("../roms/maze.rom");
+        let source = disasm::disassemble(rom, ORIGIN);
+        assert_eq!(assemble(&source).unwrap(), &rom[..]);
+    }
+
+    #[test]
+    fn labels_constants_and_expressions() {
+        let source = "
+            SPEED equ (1 << 2) + 1  ; comment
+            start:
+                LD V0, SPEED * 2
+                LD I, sprite
+                LD I, LONG sprite + 0x100
+                DRW V0, V1, end - sprite
+                JP start
+            sprite: db 0b11110000, -1, \"ab\"
+            end:
+                dw 0x1234
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![
+            0x60, 0x0a,
+            0xa2, 0x0c,
+            0xf0, 0x00, 0x03, 0x0c,
+            0xd0, 0x14,
+            0x12, 0x00,
+            0xf0, 0xff, b'a', b'b',
+            0x12, 0x34,
+        ]);
+    }
+
+    #[test]
+    fn all_words_round_trip_through_text() {
+        for w in 0..=0xffff_u16 {
+            let inst = parser::read(&w.to_be_bytes());
+            let text = inst.to_string();
+            assert_eq!(text.parse::<Instruction>(), Ok(inst), "{}", text);
+        }
+
+        let long = Instruction::LDLONG(0xbeef);
+        assert_eq!(long.to_string().parse::<Instruction>(), Ok(long));
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        let err = assemble("CLS\nLD V0, 0x100\n").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let err = assemble("JP nowhere").unwrap_err();
+        assert_eq!(err.message, "undefined symbol `nowhere`");
+
+        let err = assemble("a equ b\nb equ a\nLD V0, a").unwrap_err();
+        assert_eq!(err.message, "constants are defined in terms of themselves");
+    }
+
+    #[test]
+    fn quoted_semicolons_and_commas() {
+        let rom = assemble("LD V0, ';' ; a comment\nLD V1, ','\nDB \"a;b\", ';'").unwrap();
+        assert_eq!(rom, [0x60, b';', 0x61, b',', b'a', b';', b'b', b';']);
+    }
+}
@@ -0,0 +1,36 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use chip8::assembler;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (src, out) = match args.as_slice() {
+        [src, out] => (src, out),
+        _ => {
+            eprintln!("usage: chip8-asm <source|-> <rom>");
+            process::exit(2)
+        },
+    };
+
+    let rom = if src == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)
+            .expect("failed to read stdin data");
+        assembler::assemble(&source)
+    } else {
+        assembler::assemble_file(src)
+    };
+
+    let rom = match rom {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1)
+        },
+    };
+
+    fs::write(out, rom)
+        .expect("failed to write rom");
+}
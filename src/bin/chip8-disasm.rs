@@ -0,0 +1,21 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use chip8::{disasm, PROGRAM_START};
+
+fn main() {
+    let data = match env::args().nth(1) {
+        Some(path) => fs::read(&path)
+            .expect("failed to read rom"),
+        None => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)
+                .expect("failed to read stdin data");
+            data
+        },
+    };
+
+    let out = disasm::disassemble(&data, PROGRAM_START);
+    io::stdout().write_all(out.as_bytes())
+        .expect("failed to write disassembly");
+}
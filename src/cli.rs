@@ -0,0 +1,366 @@
+use std::fmt;
+use std::error;
+use std::path::PathBuf;
+use std::convert::TryFrom;
+
+use chip8::interpreter::{
+    PROGRAM_START,
+    DELAY_TICK_FREQ,
+    quirks::Quirks,
+    power_on::PowerOnPattern,
+    drivers::display::DEFAULT_SCALE,
+    drivers::display::render::RenderMode,
+    drivers::display::palette::{Palette, THEMES},
+    drivers::display::phosphor,
+    drivers::input::{Keymap, LAYOUTS},
+};
+
+pub const USAGE: &str = "\
+usage: chip8 [OPTIONS] [ROM]
+
+Runs ROM, or the program on standard input if ROM is - or missing.
+
+  --quirks PRESET       vip (the default), chip48, schip or xochip
+  --ips N               instructions per second, 60 to 10000000, about 500 by default
+  --load-address ADDR   where the program is loaded and starts, 0x200 by default
+  --power-on PATTERN    what memory holds before loading: zero, vip or random:SEED
+  --seed N              seeds the random numbers, to repeat a run
+  --render-mode MODE    blocks, half, quadrants, braille or scaled:N for text,
+                        sixel or kitty for images, or auto to ask the terminal
+  --scale N             the size of a pixel in sixel and kitty images
+  --palette PALETTE     default, vip, lcd, amber, or FG,BG as #rrggbb colours
+  --fade N              redraws unlit pixels take to fade out, up to 63
+  --keymap KEYMAP       qwerty, azerty, qwertz, dvorak, or the 16 keys standing
+                        for the keypad's 123C 456D 789E A0BF
+  --wav FILE            records the beeps to a WAV file
+  --debug               starts in the debugger
+  --headless            runs without a terminal, for --cycles instructions
+  --cycles N            how many instructions to run headless
+  --dump-frame FILE     writes the screen to a PBM image after a headless run
+  -h, --help            shows this
+";
+
+// where the screen goes: picked by asking the terminal, an image
+// protocol, or text in one of the character cell modes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Screen {
+    Auto,
+    Sixel,
+    Kitty,
+    Cells(RenderMode),
+}
+
+impl Screen {
+    pub fn parse(name: &str) -> Option<Screen> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Some(Screen::Auto),
+            "sixel" => Some(Screen::Sixel),
+            "kitty" => Some(Screen::Kitty),
+            _ => RenderMode::parse(name).map(Screen::Cells),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Options {
+    // None, or -, means standard input
+    pub rom: Option<PathBuf>,
+    pub quirks: Quirks,
+    // None runs at the VM's default speed
+    pub ips: Option<u32>,
+    pub load_address: u16,
+    pub power_on: PowerOnPattern,
+    // None seeds from the clock
+    pub seed: Option<u64>,
+    pub screen: Screen,
+    pub scale: u8,
+    // None leaves text in the terminal's own colours
+    pub palette: Option<Palette>,
+    pub fade: u8,
+    pub keymap: Keymap,
+    pub wav: Option<PathBuf>,
+    pub debug: bool,
+    pub headless: bool,
+    pub cycles: Option<u64>,
+    pub dump_frame: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            rom: None,
+            quirks: Quirks::default(),
+            ips: None,
+            load_address: PROGRAM_START,
+            power_on: PowerOnPattern::default(),
+            seed: None,
+            screen: Screen::Cells(RenderMode::default()),
+            scale: DEFAULT_SCALE,
+            palette: None,
+            fade: 0,
+            keymap: Keymap::default(),
+            wav: None,
+            debug: false,
+            headless: false,
+            cycles: None,
+            dump_frame: None,
+            help: false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum UsageError {
+    UnknownOption(String),
+    // an option that takes a value at the end of the arguments
+    MissingValue(String),
+    // --flag=value for an option that doesn't take one
+    UnexpectedValue(String),
+    InvalidValue { option: String, value: String, expected: String },
+    // a second ROM
+    ExtraArgument(String),
+    // an option that only means something along with another
+    Requires { option: String, needs: String },
+    Conflicts(String, String),
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsageError::UnknownOption(opt) => write!(f, "unknown option {}", opt),
+            UsageError::MissingValue(opt) => write!(f, "{} needs a value", opt),
+            UsageError::UnexpectedValue(opt) => write!(f, "{} doesn't take a value", opt),
+            UsageError::InvalidValue { option, value, expected } => {
+                write!(f, "invalid {} '{}', expected {}", option, value, expected)
+            },
+            UsageError::ExtraArgument(arg) => write!(f, "unexpected argument '{}', only one ROM can be run", arg),
+            UsageError::Requires { option, needs } => write!(f, "{} needs {}", option, needs),
+            UsageError::Conflicts(a, b) => write!(f, "{} can't be used with {}", a, b),
+        }
+    }
+}
+
+impl error::Error for UsageError {}
+
+// the options that take a value, as opposed to flags
+const VALUE_OPTIONS: [&str; 13] = [
+    "--quirks", "--ips", "--load-address", "--power-on", "--seed",
+    "--render-mode", "--scale", "--palette", "--fade", "--keymap",
+    "--wav", "--cycles", "--dump-frame",
+];
+
+// --ips: at least one instruction for each tick of the timers, and
+// no more than the host has any hope of keeping up with
+pub const MIN_IPS: u32 = DELAY_TICK_FREQ;
+pub const MAX_IPS: u32 = 10_000_000;
+
+// decimal, or hexadecimal after 0x
+fn number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// options take their value either as --option=value or as the next
+// argument; everything after -- is the ROM
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, UsageError> {
+    let mut opts = Options::default();
+    let mut args = args.into_iter();
+    let mut only_rom = false;
+
+    while let Some(arg) = args.next() {
+        if only_rom || arg == "-" || !arg.starts_with('-') {
+            if opts.rom.is_some() {
+                return Err(UsageError::ExtraArgument(arg))
+            }
+            opts.rom = Some(PathBuf::from(arg));
+            continue
+        }
+        if arg == "--" {
+            only_rom = true;
+            continue
+        }
+
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let flag = match name.as_str() {
+            "-h" | "--help" => Some(&mut opts.help),
+            "--debug" => Some(&mut opts.debug),
+            "--headless" => Some(&mut opts.headless),
+            _ => None,
+        };
+        if let Some(set) = flag {
+            if inline.is_some() {
+                return Err(UsageError::UnexpectedValue(name))
+            }
+            *set = true;
+            continue
+        }
+
+        if !VALUE_OPTIONS.contains(&name.as_str()) {
+            return Err(UsageError::UnknownOption(name))
+        }
+        let value = match inline {
+            Some(value) => value,
+            None => args.next().ok_or_else(|| UsageError::MissingValue(name.clone()))?,
+        };
+        let invalid = |expected: &str| UsageError::InvalidValue {
+            option: name.clone(),
+            value: value.clone(),
+            expected: expected.to_string(),
+        };
+
+        match name.as_str() {
+            "--quirks" => {
+                opts.quirks = Quirks::preset(&value)
+                    .ok_or_else(|| invalid("vip, chip48, schip or xochip"))?;
+            },
+            "--ips" => {
+                let ips = number(&value)
+                    .and_then(|n| u32::try_from(n).ok())
+                    .filter(|n| (MIN_IPS..=MAX_IPS).contains(n))
+                    .ok_or_else(|| invalid(&format!("{} to {} instructions per second", MIN_IPS, MAX_IPS)))?;
+                opts.ips = Some(ips);
+            },
+            "--load-address" => {
+                opts.load_address = number(&value)
+                    .and_then(|n| u16::try_from(n).ok())
+                    .ok_or_else(|| invalid("an address such as 0x600"))?;
+            },
+            "--power-on" => {
+                opts.power_on = PowerOnPattern::parse(&value)
+                    .ok_or_else(|| invalid("zero, vip or random:SEED"))?;
+            },
+            "--seed" => {
+                opts.seed = Some(number(&value).ok_or_else(|| invalid("a number"))?);
+            },
+            "--render-mode" => {
+                opts.screen = Screen::parse(&value).ok_or_else(|| invalid(
+                    "blocks, half, quadrants, braille, scaled:N, sixel, kitty or auto"
+                ))?;
+            },
+            "--scale" => {
+                opts.scale = number(&value)
+                    .and_then(|n| u8::try_from(n).ok())
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid("a size from 1 to 255"))?;
+            },
+            "--palette" => {
+                let expected = format!("{}, or FG,BG as #rrggbb colours", THEMES.join(", "));
+                opts.palette = Some(Palette::parse(&value).ok_or_else(|| invalid(&expected))?);
+            },
+            "--fade" => {
+                opts.fade = number(&value)
+                    .and_then(|n| u8::try_from(n).ok())
+                    .filter(|&n| n <= phosphor::MAX_STEPS)
+                    .ok_or_else(|| invalid(&format!("a number of redraws up to {}", phosphor::MAX_STEPS)))?;
+            },
+            "--keymap" => {
+                let layouts: Vec<&str> = LAYOUTS.iter().map(|(name, _)| *name).collect();
+                let expected = format!("{}, or 16 different keys", layouts.join(", "));
+                opts.keymap = Keymap::parse(&value).ok_or_else(|| invalid(&expected))?;
+            },
+            "--wav" => opts.wav = Some(PathBuf::from(value)),
+            "--cycles" => {
+                opts.cycles = Some(number(&value).ok_or_else(|| invalid("a number of instructions"))?);
+            },
+            "--dump-frame" => opts.dump_frame = Some(PathBuf::from(value)),
+            _ => unreachable!("{} is missing from VALUE_OPTIONS", name),
+        }
+    }
+
+    if opts.help {
+        return Ok(opts)
+    }
+    let requires = |option: &str, needs: &str| UsageError::Requires {
+        option: option.to_string(),
+        needs: needs.to_string(),
+    };
+    if opts.headless {
+        if opts.debug {
+            return Err(UsageError::Conflicts("--headless".to_string(), "--debug".to_string()))
+        }
+        if opts.cycles.is_none() {
+            return Err(requires("--headless", "--cycles"))
+        }
+    } else if opts.cycles.is_some() {
+        return Err(requires("--cycles", "--headless"))
+    } else if opts.dump_frame.is_some() {
+        return Err(requires("--dump-frame", "--headless"))
+    }
+    Ok(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Options, UsageError> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(parse_str(""), Ok(Options::default()));
+        assert_eq!(parse_str("-").unwrap().rom, Some(PathBuf::from("-")));
+    }
+
+    #[test]
+    fn values_follow_or_come_after_equals() {
+        let opts = parse_str("--quirks schip --ips=1000 pong.ch8 --load-address 0x600 --seed=42").unwrap();
+        assert_eq!(opts.rom, Some(PathBuf::from("pong.ch8")));
+        assert_eq!(opts.quirks, Quirks::SCHIP);
+        assert_eq!(opts.ips, Some(1000));
+        assert_eq!(opts.load_address, 0x600);
+        assert_eq!(opts.seed, Some(42));
+
+        let opts = parse_str("--render-mode braille --palette amber --keymap azerty --fade 4").unwrap();
+        assert_eq!(opts.screen, Screen::Cells(RenderMode::Braille));
+        assert_eq!(opts.palette, Palette::parse("amber"));
+        assert_eq!(opts.keymap, Keymap::parse("azerty").unwrap());
+        assert_eq!(opts.fade, 4);
+        assert_eq!(parse_str("--render-mode=kitty").unwrap().screen, Screen::Kitty);
+
+        let opts = parse_str("--headless --cycles 1000 --dump-frame out.pbm -- --odd-name").unwrap();
+        assert!(opts.headless);
+        assert_eq!(opts.cycles, Some(1000));
+        assert_eq!(opts.dump_frame, Some(PathBuf::from("out.pbm")));
+        assert_eq!(opts.rom, Some(PathBuf::from("--odd-name")));
+    }
+
+    #[test]
+    fn mistakes() {
+        assert_eq!(parse_str("--ipz 10"), Err(UsageError::UnknownOption("--ipz".to_string())));
+        assert_eq!(parse_str("--ipz"), Err(UsageError::UnknownOption("--ipz".to_string())));
+        assert_eq!(parse_str("--ips"), Err(UsageError::MissingValue("--ips".to_string())));
+        assert_eq!(parse_str("--debug=yes"), Err(UsageError::UnexpectedValue("--debug".to_string())));
+        assert_eq!(parse_str("a.ch8 b.ch8"), Err(UsageError::ExtraArgument("b.ch8".to_string())));
+        assert_eq!(
+            parse_str("--quirks cosmac").unwrap_err().to_string(),
+            "invalid --quirks 'cosmac', expected vip, chip48, schip or xochip"
+        );
+        assert_eq!(
+            parse_str("--ips 29").unwrap_err().to_string(),
+            "invalid --ips '29', expected 60 to 10000000 instructions per second"
+        );
+        assert_eq!(parse_str("--ips 60").unwrap().ips, Some(60));
+        assert_eq!(parse_str("--ips 10000000").unwrap().ips, Some(10_000_000));
+        for args in ["--ips 0", "--ips 59", "--ips 10000001", "--ips 4294967295", "--ips 4294967296", "--load-address 0x10000", "--scale 0", "--fade 64", "--keymap 123"].iter() {
+            assert!(matches!(parse_str(args), Err(UsageError::InvalidValue { .. })), "{}", args);
+        }
+
+        assert_eq!(parse_str("--headless").unwrap_err().to_string(), "--headless needs --cycles");
+        assert_eq!(parse_str("--cycles 10").unwrap_err().to_string(), "--cycles needs --headless");
+        assert_eq!(parse_str("--dump-frame a.pbm").unwrap_err().to_string(), "--dump-frame needs --headless");
+        assert_eq!(
+            parse_str("--headless --cycles 1 --debug").unwrap_err().to_string(),
+            "--headless can't be used with --debug"
+        );
+        // asking for help is never a mistake
+        assert!(parse_str("--cycles 10 --help").unwrap().help);
+    }
+}
@@ -0,0 +1,562 @@
+use std::io::{self, BufRead, Write};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+use crate::parser;
+use crate::instructions::Instruction;
+use crate::interpreter::{
+    VM,
+    error::VmError,
+    drivers::{Context, Display, Input, Sound, Random},
+    drivers::input::{Key, KeySet},
+};
+
+// keypad for the debugger, driven by the `press`/`release` commands;
+// for FX0A the debugger asks for a key and taps it, holding it down
+// for a single poll
+#[derive(Default)]
+pub struct DebugInput {
+    held: [bool; 16],
+    tap: Option<Key>,
+}
+
+impl Input for DebugInput {
+    fn poll_keyboard(&mut self) -> KeySet {
+        let mut keys = self.held;
+        if let Some(k) = self.tap.take() {
+            keys[k as usize] = true;
+        }
+        KeySet::from(keys)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Watch {
+    Register(u8),
+    I,
+    DT,
+    ST,
+    Ram(u16),
+}
+
+impl Watch {
+    fn parse(s: &str) -> Option<Watch> {
+        match s.to_ascii_uppercase().as_str() {
+            "I" => Some(Watch::I),
+            "DT" => Some(Watch::DT),
+            "ST" => Some(Watch::ST),
+            r if r.len() == 2 && r.starts_with('V') => {
+                u8::from_str_radix(&r[1..], 16).ok().map(Watch::Register)
+            },
+            _ => parse_number(s).map(Watch::Ram),
+        }
+    }
+
+    fn read(&self, vm: &VM) -> u16 {
+        match *self {
+            Watch::Register(r) => vm.registers()[r as usize] as u16,
+            Watch::I => vm.i(),
+            Watch::DT => vm.dt() as u16,
+            Watch::ST => vm.st() as u16,
+            Watch::Ram(addr) => vm.ram()[addr as usize] as u16,
+        }
+    }
+
+    fn name(&self) -> String {
+        match *self {
+            Watch::Register(r) => format!("V{:X}", r),
+            Watch::I => "I".to_string(),
+            Watch::DT => "DT".to_string(),
+            Watch::ST => "ST".to_string(),
+            Watch::Ram(addr) => format!("[0x{:03X}]", addr),
+        }
+    }
+}
+
+enum Stop {
+    Breakpoint(u16),
+    Watchpoint(Watch, u16, u16),
+    // the program jumped to itself, so it will never get anywhere
+    Spinning(u16),
+    // the program did something invalid, the PC is left on it
+    Fault(VmError),
+    // ran the whole budget without anything else stopping it
+    OutOfSteps(usize),
+    Exited,
+}
+
+// how many instructions `continue` and `next` run before handing
+// control back, so a program that loops forever doesn't take the
+// prompt with it
+const STEP_BUDGET: usize = 10_000_000;
+
+pub struct Debugger<R, W> {
+    commands: R,
+    out: W,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<(Watch, u16)>,
+    last: String,
+    budget: usize,
+}
+
+static HELP: &str = "\
+break ADDR        (b)   stop when PC reaches ADDR
+delete ADDR       (d)   remove the breakpoint at ADDR
+watch TARGET      (w)   stop when V0-VF, I, DT, ST or the RAM byte at ADDR changes
+unwatch TARGET          remove a watchpoint
+info                    list breakpoints and watchpoints
+step [N]          (s)   run N instructions, 1 by default
+next              (n)   like step, but runs CALLs to completion
+continue          (c)   run until a breakpoint, a watchpoint or EXIT, pausing
+                        every 10000000 instructions
+print TARGET      (p)   print V0-VF, I, DT, ST or the RAM byte at ADDR
+regs              (r)   print the registers
+stack             (bt)  print the call stack
+x ADDR [LEN]            hex dump LEN bytes of RAM, 16 by default
+list [ADDR] [N]   (l)   disassemble N instructions from ADDR, or PC
+screen                  print the screen, with # + @ for planes 1, 2 and both
+press KEY               hold down a key on the keypad
+release KEY             let go of a key
+reset                   restart the program, keeping memory as it is
+quit              (q)
+an empty line repeats the last command";
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(commands: R, out: W) -> Self {
+        Debugger {
+            commands,
+            out,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            last: String::new(),
+            budget: STEP_BUDGET,
+        }
+    }
+
+    pub fn run<D, S, G>(&mut self, vm: &mut VM, ctx: &mut Context<D, DebugInput, S, G>) -> io::Result<()>
+    where
+        D: Display,
+        S: Sound,
+        G: Random,
+    {
+        self.print_location(vm)?;
+
+        loop {
+            write!(self.out, "(chip8) ")?;
+            self.out.flush()?;
+
+            let mut line = String::new();
+            if self.commands.read_line(&mut line)? == 0 {
+                return Ok(())
+            }
+            let line = match line.trim() {
+                "" => self.last.clone(),
+                l => l.to_string(),
+            };
+            self.last = line.clone();
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (cmd, args) = match words.split_first() {
+                Some((cmd, args)) => (*cmd, args),
+                None => continue,
+            };
+
+            match (cmd, args) {
+                ("q", []) | ("quit", []) => return Ok(()),
+                ("h", []) | ("help", []) => writeln!(self.out, "{}", HELP)?,
+                ("b", [addr]) | ("break", [addr]) => match parse_number(addr) {
+                    Some(addr) => {
+                        self.breakpoints.insert(addr);
+                        writeln!(self.out, "breakpoint at 0x{:03X}", addr)?;
+                    },
+                    None => writeln!(self.out, "bad address `{}`", addr)?,
+                },
+                ("d", [addr]) | ("delete", [addr]) => match parse_number(addr) {
+                    Some(addr) if self.breakpoints.remove(&addr) => (),
+                    _ => writeln!(self.out, "no breakpoint at `{}`", addr)?,
+                },
+                ("w", [target]) | ("watch", [target]) => match Watch::parse(target) {
+                    Some(w) => {
+                        self.watchpoints.retain(|(x, _)| *x != w);
+                        self.watchpoints.push((w, w.read(vm)));
+                        writeln!(self.out, "watching {}", w.name())?;
+                    },
+                    None => writeln!(self.out, "can't watch `{}`", target)?,
+                },
+                ("unwatch", [target]) => match Watch::parse(target) {
+                    Some(w) => self.watchpoints.retain(|(x, _)| *x != w),
+                    None => writeln!(self.out, "can't watch `{}`", target)?,
+                },
+                ("info", []) => {
+                    for addr in self.breakpoints.iter() {
+                        writeln!(self.out, "breakpoint 0x{:03X}", addr)?;
+                    }
+                    for (w, _) in self.watchpoints.iter() {
+                        writeln!(self.out, "watchpoint {}", w.name())?;
+                    }
+                },
+                ("s", _) | ("step", _) => {
+                    let n = match args {
+                        [] => Some(1),
+                        [n] => parse_number(n),
+                        _ => None,
+                    };
+                    match n {
+                        Some(n) => self.step(vm, ctx, n as usize)?,
+                        None => writeln!(self.out, "usage: step [N]")?,
+                    }
+                },
+                ("n", []) | ("next", []) => self.next(vm, ctx)?,
+                ("c", []) | ("continue", []) => {
+                    let stop = self.resume(vm, ctx, vm.pc(), |_| false)?;
+                    self.report(vm, stop)?;
+                },
+                ("p", [target]) | ("print", [target]) => match Watch::parse(target) {
+                    Some(w) => writeln!(self.out, "{} = 0x{:02X}", w.name(), w.read(vm))?,
+                    None => writeln!(self.out, "can't print `{}`", target)?,
+                },
+                ("r", []) | ("regs", []) => self.print_registers(vm)?,
+                ("bt", []) | ("stack", []) => {
+                    for (depth, ret) in vm.call_stack().iter().rev().enumerate() {
+                        writeln!(self.out, "#{} returns to 0x{:03X}", depth, ret)?;
+                    }
+                },
+                ("x", [addr]) => self.dump(vm, addr, "16")?,
+                ("x", [addr, len]) => self.dump(vm, addr, len)?,
+                ("l", _) | ("list", _) => {
+                    let addr = args.first().and_then(|a| parse_number(a)).unwrap_or(vm.pc());
+                    let n = args.get(1).and_then(|n| parse_number(n)).unwrap_or(8);
+                    self.list(vm, addr, n as usize)?;
+                },
+                ("screen", []) => self.print_screen(vm)?,
+                ("reset", []) => {
+                    vm.reset();
+                    ctx.beep_end();
+                    for (w, old) in self.watchpoints.iter_mut() {
+                        *old = w.read(vm);
+                    }
+                    self.print_location(vm)?;
+                },
+                ("press", [k]) | ("release", [k]) => match parse_key(k) {
+                    Some(k) => ctx.input_mut().held[k as usize] = cmd == "press",
+                    None => writeln!(self.out, "bad key `{}`", k)?,
+                },
+                _ => writeln!(self.out, "unknown command `{}`, try `help`", line)?,
+            }
+        }
+    }
+
+    fn step<D, S, G>(&mut self, vm: &mut VM, ctx: &mut Context<D, DebugInput, S, G>, n: usize) -> io::Result<()>
+    where
+        D: Display,
+        S: Sound,
+        G: Random,
+    {
+        for _ in 0..n {
+            if vm.halted() {
+                return self.report(vm, Some(Stop::Exited))
+            }
+            if let Err(e) = self.execute(vm, ctx)? {
+                return self.report(vm, Some(Stop::Fault(e)))
+            }
+            if let Some(stop) = self.check_watchpoints(vm) {
+                return self.report(vm, Some(stop))
+            }
+        }
+        self.print_location(vm)
+    }
+
+    fn next<D, S, G>(&mut self, vm: &mut VM, ctx: &mut Context<D, DebugInput, S, G>) -> io::Result<()>
+    where
+        D: Display,
+        S: Sound,
+        G: Random,
+    {
+        match vm.current_instruction() {
+            inst @ Instruction::CALL(_) => {
+                let from = vm.pc();
+                let ret = from.wrapping_add(inst.size());
+                let sp = vm.sp();
+                if let Err(e) = self.execute(vm, ctx)? {
+                    return self.report(vm, Some(Stop::Fault(e)))
+                }
+                let stop = self.resume(vm, ctx, from, |vm| vm.pc() == ret && vm.sp() == sp)?;
+                self.report(vm, stop)
+            },
+            _ => self.step(vm, ctx, 1),
+        }
+    }
+
+    // keeps stepping until `done` holds, or something else stops it;
+    // `from` is where the user resumed, whose breakpoint is passed once
+    fn resume<D, S, G, F>(&mut self, vm: &mut VM, ctx: &mut Context<D, DebugInput, S, G>, from: u16, done: F) -> io::Result<Option<Stop>>
+    where
+        D: Display,
+        S: Sound,
+        G: Random,
+        F: Fn(&VM) -> bool,
+    {
+        let mut from = Some(from);
+        for _ in 0..self.budget {
+            if vm.halted() {
+                return Ok(Some(Stop::Exited))
+            }
+            if done(vm) {
+                return Ok(None)
+            }
+            // don't stop on the breakpoint we are resuming from
+            if from.take() != Some(vm.pc()) && self.breakpoints.contains(&vm.pc()) {
+                return Ok(Some(Stop::Breakpoint(vm.pc())))
+            }
+            if vm.current_instruction() == Instruction::JPA(vm.pc()) {
+                return Ok(Some(Stop::Spinning(vm.pc())))
+            }
+
+            if let Err(e) = self.execute(vm, ctx)? {
+                return Ok(Some(Stop::Fault(e)))
+            }
+            if let Some(stop) = self.check_watchpoints(vm) {
+                return Ok(Some(stop))
+            }
+        }
+        Ok(Some(Stop::OutOfSteps(self.budget)))
+    }
+
+    // runs the next instruction; FX0A waits for a key to go down and
+    // come back up, so unless keys are being held with `press`, or one
+    // already went down, it asks for one and taps it, which takes two
+    // cycles
+    fn execute<D, S, G>(&mut self, vm: &mut VM, ctx: &mut Context<D, DebugInput, S, G>) -> io::Result<Result<(), VmError>>
+    where
+        D: Display,
+        S: Sound,
+        G: Random,
+    {
+        if let Instruction::LDK(_) = vm.current_instruction() {
+            if vm.key_down().is_none() && !ctx.input_mut().held.contains(&true) {
+                ctx.input_mut().tap = Some(self.ask_key(vm)?);
+                if let Err(e) = vm.step(ctx) {
+                    return Ok(Err(e))
+                }
+            }
+        }
+        Ok(vm.step(ctx))
+    }
+
+    fn ask_key(&mut self, vm: &VM) -> io::Result<Key> {
+        loop {
+            write!(self.out, "0x{:03X} waits for a key (0-F): ", vm.pc())?;
+            self.out.flush()?;
+            let mut line = String::new();
+            if self.commands.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into())
+            }
+            if let Some(k) = parse_key(line.trim()) {
+                return Ok(k)
+            }
+        }
+    }
+
+    fn check_watchpoints(&mut self, vm: &VM) -> Option<Stop> {
+        let mut stop = None;
+        for (w, last) in self.watchpoints.iter_mut() {
+            let v = w.read(vm);
+            if v != *last && stop.is_none() {
+                stop = Some(Stop::Watchpoint(*w, *last, v));
+            }
+            *last = v;
+        }
+        stop
+    }
+
+    fn report(&mut self, vm: &VM, stop: Option<Stop>) -> io::Result<()> {
+        match stop {
+            Some(Stop::Breakpoint(addr)) => writeln!(self.out, "breakpoint at 0x{:03X}", addr)?,
+            Some(Stop::Watchpoint(w, old, new)) => {
+                writeln!(self.out, "{} changed: 0x{:02X} -> 0x{:02X}", w.name(), old, new)?
+            },
+            Some(Stop::Spinning(addr)) => writeln!(self.out, "0x{:03X} jumps to itself", addr)?,
+            Some(Stop::Fault(e)) => writeln!(self.out, "fault: {}", e)?,
+            Some(Stop::OutOfSteps(n)) => {
+                writeln!(self.out, "still running after {} instructions", n)?
+            },
+            Some(Stop::Exited) => {
+                writeln!(self.out, "program exited")?;
+                return Ok(())
+            },
+            None => (),
+        }
+        self.print_location(vm)
+    }
+
+    fn print_location(&mut self, vm: &VM) -> io::Result<()> {
+        writeln!(self.out, "0x{:03X}: {}", vm.pc(), vm.current_instruction())
+    }
+
+    fn print_screen(&mut self, vm: &VM) -> io::Result<()> {
+        for row in vm.framebuffer().rows() {
+            let row: String = row.iter()
+                .map(|&px| ['.', '#', '+', '@'][px as usize & 3])
+                .collect();
+            writeln!(self.out, "{}", row)?;
+        }
+        Ok(())
+    }
+
+    fn print_registers(&mut self, vm: &VM) -> io::Result<()> {
+        for (r, v) in vm.registers().iter().enumerate() {
+            let sep = if r % 8 == 7 { "\n" } else { "  " };
+            write!(self.out, "V{:X}=0x{:02X}{}", r, v, sep)?;
+        }
+        writeln!(
+            self.out,
+            "I=0x{:03X}  PC=0x{:03X}  SP={}  DT={}  ST={}",
+            vm.i(), vm.pc(), vm.sp(), vm.dt(), vm.st(),
+        )
+    }
+
+    fn dump(&mut self, vm: &VM, addr: &str, len: &str) -> io::Result<()> {
+        let (addr, len) = match (parse_number(addr), parse_number(len)) {
+            (Some(addr), Some(len)) => (addr as usize, len as usize),
+            _ => return writeln!(self.out, "usage: x ADDR [LEN]"),
+        };
+        let ram = vm.ram();
+        let end = (addr + len).min(ram.len());
+
+        for (k, row) in ram[addr.min(end)..end].chunks(16).enumerate() {
+            write!(self.out, "0x{:03X}:", addr + 16*k)?;
+            for b in row {
+                write!(self.out, " {:02X}", b)?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, vm: &VM, addr: u16, n: usize) -> io::Result<()> {
+        let ram = vm.ram();
+        let mut addr = addr as usize;
+        for _ in 0..n {
+            if addr + 2 > ram.len() {
+                break
+            }
+            let inst = parser::read(&ram[addr..]);
+            let mark = if addr == vm.pc() as usize { "=>" } else { "  " };
+            writeln!(self.out, "{} 0x{:03X}: {}", mark, addr, inst)?;
+            addr += inst.size() as usize;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(s: &str) -> Option<u16> {
+    let lower = s.to_ascii_lowercase();
+    match lower.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => lower.parse().ok(),
+    }
+}
+
+fn parse_key(s: &str) -> Option<Key> {
+    u8::from_str_radix(s, 16).ok().and_then(|k| Key::try_from(k).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::interpreter::quirks::Quirks;
+
+    // runs `commands` on `source` and returns what the debugger printed
+    fn session(source: &str, commands: &str, budget: usize) -> String {
+        let mut vm = VM::new(Quirks::VIP);
+        vm.load(assembler::assemble(source).unwrap()).unwrap();
+        let mut ctx = Context::new((), DebugInput::default(), ());
+
+        let mut out = Vec::new();
+        let mut debugger = Debugger::new(commands.as_bytes(), &mut out);
+        debugger.budget = budget;
+        debugger.run(&mut vm, &mut ctx).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    static PROGRAM: &str = "
+        LD V0, 1
+        CALL add
+        LD V1, V0
+        JP 0x206
+        add:
+            ADD V0, 2
+            RET
+    ";
+
+    #[test]
+    fn break_and_continue() {
+        let out = session(PROGRAM, "b 0x206\nc\np V1\nr\nc\n", 100);
+        assert!(out.contains("breakpoint at 0x206\n0x206:"), "{}", out);
+        assert!(out.contains("V1 = 0x03"), "{}", out);
+        assert!(out.contains("V0=0x03  V1=0x03"), "{}", out);
+        assert!(out.contains("0x206 jumps to itself"), "{}", out);
+    }
+
+    #[test]
+    fn step_and_next() {
+        let out = session(PROGRAM, "s\nn\nr\n", 100);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "(chip8) 0x202: CALL 0x208");
+        // next ran the whole call, and stopped after it
+        assert_eq!(lines[2], "(chip8) 0x204: LD V1, V0");
+        assert!(out.contains("V0=0x03  V1=0x00"), "{}", out);
+
+        let out = session(PROGRAM, "s 2\nbt\n", 100);
+        assert!(out.contains("0x208: ADD"), "{}", out);
+        assert!(out.contains("#0 returns to 0x204"), "{}", out);
+    }
+
+    #[test]
+    fn next_stops_in_the_call_on_a_breakpoint() {
+        let out = session(PROGRAM, "s\nb 0x208\nn\nn\n", 100);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[2], "(chip8) breakpoint at 0x208");
+        assert_eq!(lines[3], "(chip8) breakpoint at 0x208");
+        assert_eq!(lines[4], "0x208: ADD V0, 0x02");
+        // and the next one carries on from there
+        assert_eq!(lines[5], "(chip8) 0x20A: RET");
+    }
+
+    #[test]
+    fn continue_stops_again_where_it_resumed() {
+        // the loop comes back round to the breakpoint it left
+        let out = session("loop: ADD V0, 1\nJP loop", "b 0x200\nc\nc\nr\n", 100);
+        assert_eq!(out.matches("breakpoint at 0x200\n0x200:").count(), 2, "{}", out);
+        assert!(out.contains("V0=0x02"), "{}", out);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let out = session(PROGRAM, "watch V1\nc\n", 100);
+        assert!(out.contains("watching V1"), "{}", out);
+        assert!(out.contains("V1 changed: 0x00 -> 0x03\n0x206:"), "{}", out);
+    }
+
+    #[test]
+    fn asks_for_a_key() {
+        let out = session("LD V3, K\nJP 0x202", "s\nG\n7\nr\n", 100);
+        assert!(out.contains("0x200 waits for a key (0-F): 0x200 waits"), "{}", out);
+        assert!(out.contains("V3=0x07"), "{}", out);
+        assert!(out.contains("PC=0x202"), "{}", out);
+
+        // held keys are left to the program
+        let out = session("LD V3, K\nJP 0x202", "press 2\ns\nrelease 2\ns\nr\n", 100);
+        assert!(!out.contains("waits for a key"), "{}", out);
+        assert!(out.contains("V3=0x02"), "{}", out);
+    }
+
+    #[test]
+    fn endless_loops_give_the_prompt_back() {
+        let out = session("loop: ADD V0, 1\nJP loop", "c\nc\nr\n", 1000);
+        assert_eq!(out.matches("still running after 1000 instructions").count(), 2, "{}", out);
+        // two budgets of ADD and JP make a thousand ADDs
+        assert!(out.contains("V0=0xE8"), "{}", out);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::Write;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::parser;
+use crate::instructions::Instruction;
+
+// disassembles `rom`, loaded at `origin`; the output is valid
+// assembler input, with addresses and raw words in comments
+pub fn disassemble(rom: &[u8], origin: u16) -> String {
+    let code = trace(rom, origin);
+    let mut labels = BTreeSet::new();
+
+    for inst in code.values() {
+        if let Some(target) = branch_target(inst) {
+            if in_rom(rom, origin, target) {
+                labels.insert(target);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let end = origin as usize + rom.len();
+
+    // labels that land in the middle of another instruction can't
+    // be placed inline, so they are defined as constants instead
+    let mut placed = BTreeSet::new();
+    let mut addr = origin as usize;
+    while addr < end {
+        match code.get(&(addr as u16)) {
+            Some(inst) => {
+                placed.insert(addr as u16);
+                addr += inst.size() as usize;
+            },
+            None => addr += 1,
+        }
+    }
+    for label in labels.difference(&placed) {
+        let _ = writeln!(out, "{} equ 0x{:03X}", label_name(*label), label);
+    }
+
+    let mut addr = origin as usize;
+    while addr < end {
+        if let Some(inst) = code.get(&(addr as u16)) {
+            if labels.contains(&(addr as u16)) {
+                let _ = writeln!(out, "{}:", label_name(addr as u16));
+            }
+
+            let size = inst.size() as usize;
+            let raw = match *inst {
+                Instruction::LDLONG(a) => format!("{:04X} {:04X}", inst.encode(), a),
+                _ => format!("{:04X}", inst.encode()),
+            };
+            let text = match *inst {
+                Instruction::JPA(a) if labels.contains(&a) => {
+                    format!("JP {}", label_name(a))
+                },
+                Instruction::CALL(a) if labels.contains(&a) => {
+                    format!("CALL {}", label_name(a))
+                },
+                inst => inst.to_string(),
+            };
+
+            let _ = writeln!(out, "    {:<23} ; 0x{:03X}  {}", text, addr, raw);
+            addr += size;
+            continue
+        }
+
+        // everything that isn't reachable code is emitted as data,
+        // up to 8 bytes per line and stopping at the next instruction
+        let mut n = 0;
+        while n < 8 && addr + n < end && !code.contains_key(&((addr + n) as u16)) {
+            n += 1;
+        }
+        let off = addr - origin as usize;
+        let bytes: Vec<String> = rom[off..off+n]
+            .iter()
+            .map(|b| format!("0x{:02X}", b))
+            .collect();
+        let text = format!("db {}", bytes.join(", "));
+        let _ = writeln!(out, "    {:<23} ; 0x{:03X}", text, addr);
+        addr += n;
+    }
+
+    out
+}
+
+// follows every path of execution from `origin`, returning the
+// instructions that were reached, keyed by their address
+fn trace(rom: &[u8], origin: u16) -> BTreeMap<u16, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![origin];
+
+    while let Some(addr) = pending.pop() {
+        if code.contains_key(&addr) {
+            continue
+        }
+
+        let inst = match decode(rom, origin, addr) {
+            Some(Instruction::UNKNOWN(_)) | None => continue,
+            Some(inst) => inst,
+        };
+        code.insert(addr, inst);
+
+        let next = addr.wrapping_add(inst.size());
+        match inst {
+            Instruction::RET | Instruction::EXIT => (),
+            // the target depends on a register, so we can't follow it
+            Instruction::JPAFAR(_) => (),
+            Instruction::JPA(target) => pending.push(target),
+            Instruction::CALL(target) => {
+                pending.push(target);
+                pending.push(next);
+            },
+            Instruction::SEI(..) | Instruction::SNEI(..) |
+            Instruction::SER(..) | Instruction::SNER(..) |
+            Instruction::SKP(_) | Instruction::SKNP(_) => {
+                pending.push(next);
+                if let Some(skipped) = decode(rom, origin, next) {
+                    pending.push(next.wrapping_add(skipped.size()));
+                }
+            },
+            _ => pending.push(next),
+        }
+    }
+
+    code
+}
+
+fn decode(rom: &[u8], origin: u16, addr: u16) -> Option<Instruction> {
+    let off = addr.checked_sub(origin)? as usize;
+    if off + 2 > rom.len() {
+        return None
+    }
+    Some(parser::read(&rom[off..]))
+}
+
+fn in_rom(rom: &[u8], origin: u16, addr: u16) -> bool {
+    addr >= origin && ((addr - origin) as usize) < rom.len()
+}
+
+fn branch_target(inst: &Instruction) -> Option<u16> {
+    match *inst {
+        Instruction::JPA(a) | Instruction::CALL(a) => Some(a),
+        _ => None,
+    }
+}
+
+fn label_name(addr: u16) -> String {
+    format!("L{:03X}", addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+
+    #[test]
+    fn labels_for_jumps_and_calls() {
+        // CALL 0x206; JP 0x202; then a RET past two bytes of data
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xee];
+        assert_eq!(disassemble(&rom, 0x200), concat!(
+            "    CALL L206               ; 0x200  2206\n",
+            "L202:\n",
+            "    JP L202                 ; 0x202  1202\n",
+            "    db 0x00, 0x00           ; 0x204\n",
+            "L206:\n",
+            "    RET                     ; 0x206  00EE\n",
+        ));
+    }
+
+    #[test]
+    fn targets_inside_instructions() {
+        // CALL 0x208; LD I, LONG 0x1204; EXIT; JP 0x204, which jumps
+        // into the address of the LD I, LONG, where it reads JP 0x204
+        let rom = [0x22, 0x08, 0xf0, 0x00, 0x12, 0x04, 0x00, 0xfd, 0x12, 0x04];
+        let source = disassemble(&rom, 0x200);
+        assert_eq!(source, concat!(
+            "L204 equ 0x204\n",
+            "    CALL L208               ; 0x200  2208\n",
+            "    LD I, LONG 0x1204       ; 0x202  F000 1204\n",
+            "    EXIT                    ; 0x206  00FD\n",
+            "L208:\n",
+            "    JP L204                 ; 0x208  1204\n",
+        ));
+        assert_eq!(assembler::assemble(&source).unwrap(), rom);
+    }
+
+    #[test]
+    fn skips_over_long_loads() {
+        // SE V0, 0; LD I, LONG 0x1234; EXIT: the skip lands on the
+        // EXIT, not on the 1234 half way through, which reads JP 0x234
+        let rom = [0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x00, 0xfd];
+        let code = trace(&rom, 0x200);
+        assert_eq!(code.keys().copied().collect::<Vec<_>>(), [0x200, 0x202, 0x206]);
+        assert_eq!(disassemble(&rom, 0x200), concat!(
+            "    SE V0, 0x00             ; 0x200  3000\n",
+            "    LD I, LONG 0x1234       ; 0x202  F000 1234\n",
+            "    EXIT                    ; 0x206  00FD\n",
+        ));
+    }
+
+    #[test]
+    fn data_that_is_never_run() {
+        // JP 0x200, then 10 bytes of data, in lines of up to 8
+        let rom = [0x12, 0x00, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let source = disassemble(&rom, 0x200);
+        assert_eq!(source, concat!(
+            "L200:\n",
+            "    JP L200                 ; 0x200  1200\n",
+            "    db 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08 ; 0x202\n",
+            "    db 0x09, 0x0A           ; 0x20A\n",
+        ));
+        assert_eq!(assembler::assemble(&source).unwrap(), rom);
+    }
+}
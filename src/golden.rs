@@ -0,0 +1,179 @@
+// Golden-image tests: a ROM runs without a display for a fixed number of cycles,
+// with a fixed RNG seed and scripted key presses, and the screen it ends
+// up with is compared against a plain PBM image in tests/golden.
+//
+// When they differ, the actual screen and a diff image are written to
+// target/golden: pixels only in the golden image are red, pixels only in
+// the actual one are green. Run with CHIP8_BLESS=1 to (re)write the
+// golden images instead.
+
+use std::env;
+use std::fs;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::collections::VecDeque;
+
+use crate::interpreter::{
+    VM,
+    quirks::Quirks,
+    drivers::{Context, Input},
+    drivers::input::{Key, KeySet},
+    drivers::random::Xorshift,
+};
+
+// a key goes down or up once `cycle` instructions have run
+#[derive(Copy, Clone, Debug)]
+struct KeyEvent {
+    cycle: u64,
+    key: Key,
+    down: bool,
+}
+
+const fn press(cycle: u64, key: Key) -> KeyEvent {
+    KeyEvent { cycle, key, down: true }
+}
+
+const fn release(cycle: u64, key: Key) -> KeyEvent {
+    KeyEvent { cycle, key, down: false }
+}
+
+struct ScriptedInput {
+    script: VecDeque<KeyEvent>,
+    held: [bool; 16],
+}
+
+impl ScriptedInput {
+    fn new(script: &[KeyEvent]) -> Self {
+        let mut script: Vec<_> = script.to_vec();
+        script.sort_by_key(|e| e.cycle);
+        ScriptedInput { script: script.into(), held: [false; 16] }
+    }
+
+    fn advance(&mut self, cycle: u64) {
+        while let Some(e) = self.script.front().filter(|e| e.cycle <= cycle) {
+            self.held[e.key as usize] = e.down;
+            self.script.pop_front();
+        }
+    }
+}
+
+impl Input for ScriptedInput {
+    fn poll_keyboard(&mut self) -> KeySet {
+        KeySet::from(self.held)
+    }
+}
+
+struct Golden<'a> {
+    name: &'a str,
+    rom: &'a [u8],
+    quirks: Quirks,
+    cycles: u64,
+    seed: u64,
+    keys: &'a [KeyEvent],
+}
+
+impl Golden<'_> {
+    fn check(&self) {
+        let mut vm = VM::new(self.quirks);
+        vm.load(self.rom).unwrap();
+        let input = ScriptedInput::new(self.keys);
+        let random = Xorshift::new(self.seed);
+        let mut ctx = Context::with_random((), input, (), random);
+
+        for cycle in 0..self.cycles {
+            ctx.input_mut().advance(cycle);
+            vm.step(&mut ctx).unwrap();
+        }
+
+        let actual = vm.framebuffer().to_pbm();
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let golden_path = dir.join("tests/golden").join(format!("{}.pbm", self.name));
+
+        if env::var_os("CHIP8_BLESS").is_some() {
+            fs::write(&golden_path, &actual).unwrap();
+            return
+        }
+
+        let golden = fs::read_to_string(&golden_path)
+            .unwrap_or_else(|e| panic!("{}: {}", golden_path.display(), e));
+        if golden == actual {
+            return
+        }
+
+        let out = dir.join("target/golden");
+        fs::create_dir_all(&out).unwrap();
+        let actual_path = out.join(format!("{}.pbm", self.name));
+        let diff_path = out.join(format!("{}.diff.ppm", self.name));
+        fs::write(&actual_path, &actual).unwrap();
+        match diff(&golden, &actual) {
+            Some(img) => fs::write(&diff_path, img).unwrap(),
+            None => panic!(
+                "{}: screen size differs from {}, see {}",
+                self.name, golden_path.display(), actual_path.display(),
+            ),
+        }
+        panic!(
+            "{}: screen differs from {}, see {} and {}",
+            self.name, golden_path.display(), actual_path.display(), diff_path.display(),
+        );
+    }
+}
+
+// the images as written by Framebuffer::to_pbm: the size line, then the rows
+fn parse_pbm(pbm: &str) -> Option<(&str, Vec<&str>)> {
+    let mut lines = pbm.lines();
+    if lines.next()? != "P1" {
+        return None
+    }
+    let size = lines.next()?;
+    Some((size, lines.collect()))
+}
+
+fn diff(golden: &str, actual: &str) -> Option<String> {
+    let (size, golden) = parse_pbm(golden)?;
+    let (actual_size, actual) = parse_pbm(actual)?;
+    if size != actual_size || golden.len() != actual.len() {
+        return None
+    }
+
+    let mut out = format!("P3\n{}\n255\n", size);
+    for (g, a) in golden.iter().zip(actual.iter()) {
+        for (g, a) in g.bytes().zip(a.bytes()) {
+            let rgb = match (g, a) {
+                (b'1', b'1') => "255 255 255",
+                (b'1', _) => "255 0 0",
+                (_, b'1') => "0 255 0",
+                _ => "0 0 0",
+            };
+            let _ = writeln!(out, "{}", rgb);
+        }
+    }
+    Some(out)
+}
+
+#[test]
+fn maze() {
+    Golden {
+        name: "maze",
+        rom: include_bytes!("../roms/maze.rom"),
+        quirks: Quirks::default(),
+        cycles: 2000,
+        seed: 0xc8,
+        keys: &[],
+    }.check();
+}
+
+// LD V0, K; LD F, V0; DRW V0, V0, 5; then spins
+const SHOW_KEY: [u8; 10] = [0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06, 0x00, 0x00];
+
+#[test]
+fn waits_for_key() {
+    Golden {
+        name: "waits_for_key",
+        rom: &SHOW_KEY,
+        quirks: Quirks::default(),
+        cycles: 100,
+        seed: 0,
+        keys: &[press(50, Key::A), release(60, Key::A)],
+    }.check();
+}
@@ -1,9 +1,12 @@
+use core::fmt;
+
 pub type Address = u16;
 pub type Immediate = u8;
 pub type Register = u8;
 
 // order as in 3.1 of:
 // http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Instruction {
     UNKNOWN(u16),
@@ -37,8 +40,196 @@ pub enum Instruction {
     LDTS(Register),
     LDSS(Register),
     ADDA(Register),
-    LDDIG(Immediate),
+    LDDIG(Register),
     LDBCD(Register),
     LDREGST(Register),
     LDREGRD(Register),
+
+    // SUPER-CHIP 1.1, as in:
+    // http://devernay.free.fr/hacks/chip8/schip.txt
+    SCD(Immediate),
+    SCR,
+    SCL,
+    EXIT,
+    LOW,
+    HIGH,
+    LDHDIG(Register),
+    LDRPLST(Register),
+    LDRPLRD(Register),
+
+    // XO-CHIP, as in:
+    // https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
+    LDLONG(Address),
+    LDRNGST(Register, Register),
+    LDRNGRD(Register, Register),
+    PLANE(Immediate),
+    SCU(Immediate),
+    AUDIO,
+    PITCH(Register),
+}
+
+impl Instruction {
+    // size in bytes, which is 2 for everything but F000 NNNN
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LDLONG(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // opcode word; F000 NNNN is followed by a second word holding NNNN
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let xy = |op: u16, x: Register, y: Register, n: u16| {
+            op | (x as u16) << 8 | (y as u16) << 4 | n
+        };
+        let xnn = |op: u16, x: Register, nn: u8| op | (x as u16) << 8 | nn as u16;
+        let x = |op: u16, x: Register| op | (x as u16) << 8;
+
+        match *self {
+            UNKNOWN(w) => w,
+            CLS => 0x00e0,
+            RET => 0x00ee,
+            JPA(a) => 0x1000 | a,
+            CALL(a) => 0x2000 | a,
+            SEI(r, b) => xnn(0x3000, r, b),
+            SNEI(r, b) => xnn(0x4000, r, b),
+            SER(rx, ry) => xy(0x5000, rx, ry, 0),
+            LDI(r, b) => xnn(0x6000, r, b),
+            ADDI(r, b) => xnn(0x7000, r, b),
+            LDR(rx, ry) => xy(0x8000, rx, ry, 0),
+            ORR(rx, ry) => xy(0x8000, rx, ry, 1),
+            ANDR(rx, ry) => xy(0x8000, rx, ry, 2),
+            XORR(rx, ry) => xy(0x8000, rx, ry, 3),
+            ADDR(rx, ry) => xy(0x8000, rx, ry, 4),
+            SUBR(rx, ry) => xy(0x8000, rx, ry, 5),
+            SHRR(rx, ry) => xy(0x8000, rx, ry, 6),
+            SUBNR(rx, ry) => xy(0x8000, rx, ry, 7),
+            SHLR(rx, ry) => xy(0x8000, rx, ry, 0xe),
+            SNER(rx, ry) => xy(0x9000, rx, ry, 0),
+            LDA(a) => 0xa000 | a,
+            JPAFAR(a) => 0xb000 | a,
+            RND(r, b) => xnn(0xc000, r, b),
+            DRW(rx, ry, n) => xy(0xd000, rx, ry, n as u16),
+            SKP(r) => x(0xe09e, r),
+            SKNP(r) => x(0xe0a1, r),
+            LDTG(r) => x(0xf007, r),
+            LDK(r) => x(0xf00a, r),
+            LDTS(r) => x(0xf015, r),
+            LDSS(r) => x(0xf018, r),
+            ADDA(r) => x(0xf01e, r),
+            LDDIG(r) => x(0xf029, r),
+            LDBCD(r) => x(0xf033, r),
+            LDREGST(r) => x(0xf055, r),
+            LDREGRD(r) => x(0xf065, r),
+            SCD(n) => 0x00c0 | n as u16,
+            SCR => 0x00fb,
+            SCL => 0x00fc,
+            EXIT => 0x00fd,
+            LOW => 0x00fe,
+            HIGH => 0x00ff,
+            LDHDIG(r) => x(0xf030, r),
+            LDRPLST(r) => x(0xf075, r),
+            LDRPLRD(r) => x(0xf085, r),
+            LDLONG(_) => 0xf000,
+            LDRNGST(rx, ry) => xy(0x5000, rx, ry, 2),
+            LDRNGRD(rx, ry) => xy(0x5000, rx, ry, 3),
+            PLANE(n) => x(0xf001, n),
+            SCU(n) => 0x00d0 | n as u16,
+            AUDIO => 0xf002,
+            PITCH(r) => x(0xf03a, r),
+        }
+    }
+}
+
+// mnemonics as in 3.1 of Cowgod's reference, extended for
+// SUPER-CHIP and XO-CHIP; words that don't decode are printed as data
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            UNKNOWN(w) => write!(f, "dw 0x{:04X}", w),
+            CLS => write!(f, "CLS"),
+            RET => write!(f, "RET"),
+            JPA(a) => write!(f, "JP 0x{:03X}", a),
+            CALL(a) => write!(f, "CALL 0x{:03X}", a),
+            SEI(x, b) => write!(f, "SE V{:X}, 0x{:02X}", x, b),
+            SNEI(x, b) => write!(f, "SNE V{:X}, 0x{:02X}", x, b),
+            SER(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LDI(x, b) => write!(f, "LD V{:X}, 0x{:02X}", x, b),
+            ADDI(x, b) => write!(f, "ADD V{:X}, 0x{:02X}", x, b),
+            LDR(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            ORR(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            ANDR(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            XORR(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            ADDR(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            SUBR(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            SHRR(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SUBNR(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            SHLR(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SNER(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LDA(a) => write!(f, "LD I, 0x{:03X}", a),
+            JPAFAR(a) => write!(f, "JP V0, 0x{:03X}", a),
+            RND(x, b) => write!(f, "RND V{:X}, 0x{:02X}", x, b),
+            DRW(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SKP(x) => write!(f, "SKP V{:X}", x),
+            SKNP(x) => write!(f, "SKNP V{:X}", x),
+            LDTG(x) => write!(f, "LD V{:X}, DT", x),
+            LDK(x) => write!(f, "LD V{:X}, K", x),
+            LDTS(x) => write!(f, "LD DT, V{:X}", x),
+            LDSS(x) => write!(f, "LD ST, V{:X}", x),
+            ADDA(x) => write!(f, "ADD I, V{:X}", x),
+            LDDIG(x) => write!(f, "LD F, V{:X}", x),
+            LDBCD(x) => write!(f, "LD B, V{:X}", x),
+            LDREGST(x) => write!(f, "LD [I], V{:X}", x),
+            LDREGRD(x) => write!(f, "LD V{:X}, [I]", x),
+            SCD(n) => write!(f, "SCD {}", n),
+            SCR => write!(f, "SCR"),
+            SCL => write!(f, "SCL"),
+            EXIT => write!(f, "EXIT"),
+            LOW => write!(f, "LOW"),
+            HIGH => write!(f, "HIGH"),
+            LDHDIG(x) => write!(f, "LD HF, V{:X}", x),
+            LDRPLST(x) => write!(f, "LD R, V{:X}", x),
+            LDRPLRD(x) => write!(f, "LD V{:X}, R", x),
+            LDLONG(a) => write!(f, "LD I, LONG 0x{:04X}", a),
+            LDRNGST(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LDRNGRD(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            PLANE(n) => write!(f, "PLANE {}", n),
+            SCU(n) => write!(f, "SCU {}", n),
+            AUDIO => write!(f, "AUDIO"),
+            PITCH(x) => write!(f, "PITCH V{:X}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn all_words_round_trip_through_encode() {
+        for w in 0..=0xffff_u16 {
+            let inst = parser::read(&w.to_be_bytes());
+            assert_eq!(inst.encode(), w, "{:?}", inst);
+        }
+    }
+
+    #[test]
+    fn unknown_words_are_preserved() {
+        for &w in &[0x0000, 0x5001, 0x800f, 0xe000, 0xf0ff, 0xf000] {
+            let inst = parser::read(&u16::to_be_bytes(w));
+            assert_eq!(inst, Instruction::UNKNOWN(w));
+            assert_eq!(inst.encode(), w);
+        }
+    }
+
+    #[test]
+    fn long_load_spans_two_words() {
+        let inst = parser::read(&[0xf0, 0x00, 0x12, 0x34]);
+        assert_eq!(inst, Instruction::LDLONG(0x1234));
+        assert_eq!((inst.encode(), inst.size()), (0xf000, 4));
+    }
 }
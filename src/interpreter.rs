@@ -1,14 +1,23 @@
 pub mod drivers;
+pub mod error;
+pub mod framebuffer;
+pub mod quirks;
+pub mod power_on;
+#[cfg(feature = "std")]
+pub mod savestate;
+pub mod scheduler;
+#[cfg(test)]
+mod opcodes;
 
-use std::time::Duration;
-use std::mem::MaybeUninit;
-use std::convert::TryFrom;
-use std::thread;
+use core::convert::TryFrom;
 
-use crate::rand;
 use crate::parser;
-use crate::instructions::Instruction;
+use crate::instructions::{Instruction, Register};
 use drivers::*;
+use error::VmError;
+use framebuffer::{Framebuffer, PLANES};
+use quirks::{Quirks, LoadStore};
+use power_on::PowerOnPattern;
 
 pub struct VM {
     reg_snd: u8,
@@ -16,105 +25,357 @@ pub struct VM {
     reg_sp: u8,
     reg_i: u16,
     reg_pc: u16,
-    registers: MaybeUninit<[u8; 16]>,
-    stack: MaybeUninit<[u16; 16]>,
-    ram: MaybeUninit<[u8; 4096]>,
+    registers: [u8; 16],
+    stack: [u16; 16],
+    ram: [u8; RAM_SIZE],
+    fb: Framebuffer,
+    // whether the screen changed since it was last presented
+    dirty: bool,
+    rpl: [u8; 16],
+    pattern: [u8; 16],
+    pitch: u8,
+    planes: u8,
+    // cycles run since the timers were last decremented
+    cycles: u32,
+    cycles_per_frame: u32,
+    // set by DXYN with the display wait quirk, to end the frame
+    vblank_wait: bool,
+    // the key FX0A saw go down, and is waiting to come back up
+    key_down: Option<input::Key>,
+    halted: bool,
+    quirks: Quirks,
+    power_on: PowerOnPattern,
+    // where the program was loaded, and where it starts
+    entry: u16,
 }
 
 impl VM {
-    pub const fn new() -> VM {
-        VM {
+    // a machine that powers on with everything zeroed
+    pub fn new(quirks: Quirks) -> VM {
+        Self::with_power_on(quirks, PowerOnPattern::Zero)
+    }
+
+    pub fn with_power_on(quirks: Quirks, power_on: PowerOnPattern) -> VM {
+        let mut vm = VM {
             reg_snd: 0,
             reg_dt: 0,
             reg_sp: 0,
             reg_i: 0,
             reg_pc: 0,
-            registers: MaybeUninit::uninit(),
-            stack: MaybeUninit::uninit(),
-            ram: MaybeUninit::uninit(),
+            registers: [0; 16],
+            stack: [0; 16],
+            ram: [0; RAM_SIZE],
+            fb: Framebuffer::new(),
+            dirty: false,
+            rpl: [0; 16],
+            pattern: [0; 16],
+            pitch: 64,
+            planes: 1,
+            cycles: 0,
+            cycles_per_frame: CYCLES_PER_FRAME,
+            vblank_wait: false,
+            key_down: None,
+            halted: false,
+            quirks,
+            power_on,
+            entry: PROGRAM_START,
+        };
+        vm.hard_reset();
+        vm
+    }
+
+    // like the reset switch: the CPU, the timers and the screen start
+    // over at the program's start, and memory, with the program in it, is kept; a
+    // beep that was playing is up to the host to stop
+    pub fn reset(&mut self) {
+        self.reg_snd = 0;
+        self.reg_dt = 0;
+        self.reg_sp = 0;
+        self.reg_i = 0;
+        self.reg_pc = self.entry;
+        self.registers = [0; 16];
+        self.stack = [0; 16];
+        self.fb = Framebuffer::new();
+        self.dirty = true;
+        self.pattern = [0; 16];
+        self.pitch = 64;
+        self.planes = 1;
+        self.cycles = 0;
+        self.vblank_wait = false;
+        self.key_down = None;
+        self.halted = false;
+    }
+
+    // like switching the machine off and on: memory, the registers and
+    // the stack get the power-on pattern, with the fonts loaded over
+    // it, so the program has to be loaded again
+    pub fn hard_reset(&mut self) {
+        self.reset();
+        self.rpl = [0; 16];
+        self.power_on.fill(&mut self.ram, &mut self.registers, &mut self.stack);
+        self.load_fonts();
+    }
+
+    fn load_fonts(&mut self) {
+        self.ram[0..80].copy_from_slice(&FONT[..]);
+        self.ram[BIG_FONT_ADDR..BIG_FONT_ADDR+160].copy_from_slice(&BIG_FONT[..]);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.reg_pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.reg_i
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.reg_sp
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.reg_dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.reg_snd
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    // the key FX0A saw pressed, while it waits for its release
+    pub fn key_down(&self) -> Option<input::Key> {
+        self.key_down
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    // how many instructions run in each 60 Hz frame, at least one;
+    // this sets the speed of the CPU but not that of the timers
+    pub fn set_cycles_per_frame(&mut self, n: u32) {
+        self.cycles_per_frame = n.max(1);
+    }
+
+    // return addresses of the calls in progress, outermost first
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack()[..self.reg_sp as usize]
+    }
+
+    pub fn current_instruction(&self) -> Instruction {
+        let pc = self.reg_pc as usize;
+        match self.ram().get(pc..pc+2) {
+            Some(_) => parser::read(&self.ram()[pc..]),
+            None => Instruction::UNKNOWN(self.ram()[pc] as u16),
         }
     }
 
-    fn registers(&self) -> &[u8; 16] {
-        unsafe { &*self.registers.as_ptr() }
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.fb
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
     }
 
     fn registers_mut(&mut self) -> &mut [u8; 16] {
-        unsafe { &mut *self.registers.as_mut_ptr() }
+        &mut self.registers
     }
 
     fn stack(&self) -> &[u16; 16] {
-        unsafe { &*self.stack.as_ptr() }
+        &self.stack
     }
 
     fn stack_mut(&mut self) -> &mut [u16; 16] {
-        unsafe { &mut *self.stack.as_mut_ptr() }
+        &mut self.stack
     }
 
-    fn ram(&self) -> &[u8; 4096] {
-        unsafe { &*self.ram.as_ptr() }
+    pub fn ram(&self) -> &[u8; RAM_SIZE] {
+        &self.ram
     }
 
-    fn ram_mut(&mut self) -> &mut [u8; 4096] {
-        unsafe { &mut *self.ram.as_mut_ptr() }
+    fn ram_mut(&mut self) -> &mut [u8; RAM_SIZE] {
+        &mut self.ram
     }
 
-    pub fn load<T: AsRef<[u8]>>(&mut self, program: T) {
+    pub fn load<T: AsRef<[u8]>>(&mut self, program: T) -> Result<(), VmError> {
+        self.load_at(PROGRAM_START, program)
+    }
+
+    // loads the program at `addr` and starts it there, for programs
+    // written for machines such as the ETI 660, which start at 0x600
+    pub fn load_at<T: AsRef<[u8]>>(&mut self, addr: u16, program: T) -> Result<(), VmError> {
         let prog = program.as_ref();
-        self.reg_pc = 0x200;
-        (&mut self.ram_mut()[0..80]).copy_from_slice(&FONT[..]);
-        (&mut self.ram_mut()[0x200..0x200+prog.len()]).copy_from_slice(prog);
+        let start = addr as usize;
+        let max = RAM_SIZE - start;
+        if prog.len() > max {
+            return Err(VmError::RomTooLarge { size: prog.len(), max })
+        }
+        self.entry = addr;
+        self.reg_pc = addr;
+        self.halted = false;
+        self.fb = Framebuffer::new();
+        self.dirty = true;
+        self.load_fonts();
+        self.ram_mut()[start..start+prog.len()].copy_from_slice(prog);
+        Ok(())
     }
 
-    pub fn run<D, I, S>(&mut self, ctx: &mut Context<D, I, S>)
+    // runs a single instruction; nothing here sleeps, pacing is up to the host
+    pub fn step<D, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>) -> Result<(), VmError>
     where
         D: Display,
         I: Input,
         S: Sound,
+        R: Random,
     {
-        let mut i = 0;
-        loop {
-            thread::sleep(CPU_DELAY);
-            if i == DECREMENT {
-                i = 0;
-                let dt = self.reg_dt;
-                let snd = self.reg_snd;
-                self.reg_dt -= DECREMENT as u8;
-                self.reg_snd -= DECREMENT as u8;
-                if self.reg_dt > dt {
-                    self.reg_dt = 0;
-                }
-                if self.reg_snd > snd {
-                    self.reg_snd = 0;
-                }
-                if self.reg_snd == 0 {
-                    ctx.beep_end();
-                }
+        self.cycle(ctx).map(|_| ())
+    }
+
+    // runs an instruction, returning whether it was the last of its frame
+    fn cycle<D, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>) -> Result<bool, VmError>
+    where
+        D: Display,
+        I: Input,
+        S: Sound,
+        R: Random,
+    {
+        if self.halted {
+            return Ok(false)
+        }
+        self.interpret_cycle(ctx)?;
+        if self.halted {
+            self.present(ctx);
+        }
+
+        // the timers tick once at the end of each frame, which ends
+        // early if a sprite was drawn while waiting for the display
+        self.cycles += 1;
+        let vblank = core::mem::take(&mut self.vblank_wait);
+        if self.cycles < self.cycles_per_frame && !vblank {
+            return Ok(false)
+        }
+        self.cycles = 0;
+        self.present(ctx);
+        ctx.end_frame();
+        self.reg_dt = self.reg_dt.saturating_sub(1);
+        if self.reg_snd > 0 {
+            self.reg_snd -= 1;
+            if self.reg_snd == 0 {
+                ctx.beep_end();
             }
-            self.interpret_cycle(ctx);
-            i += 1;
         }
+        Ok(true)
     }
 
-    fn interpret_cycle<D, I, S>(&mut self, ctx: &mut Context<D, I, S>)
+    fn present<D: Display, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>) {
+        if self.dirty {
+            self.dirty = false;
+            ctx.present(&self.fb);
+        }
+    }
+
+    // runs up to `n` instructions, stopping early if the program exits
+    pub fn run_cycles<D, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>, n: u64) -> Result<(), VmError>
+    where
+        D: Display,
+        I: Input,
+        S: Sound,
+        R: Random,
+    {
+        for _ in 0..n {
+            if self.halted {
+                break
+            }
+            self.step(ctx)?;
+        }
+        Ok(())
+    }
+
+    // runs the rest of the current 60 Hz frame, a full one unless
+    // step() was used in between, then decrements the timers once
+    pub fn run_frame<D, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>) -> Result<(), VmError>
+    where
+        D: Display,
+        I: Input,
+        S: Sound,
+        R: Random,
+    {
+        while !self.halted {
+            if self.cycle(ctx)? {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    // runs instructions until `done` holds before the next one,
+    // or the program exits
+    pub fn run_until<D, I, S, R, F>(&mut self, ctx: &mut Context<D, I, S, R>, mut done: F) -> Result<(), VmError>
+    where
+        D: Display,
+        I: Input,
+        S: Sound,
+        R: Random,
+        F: FnMut(&VM) -> bool,
+    {
+        while !self.halted && !done(self) {
+            self.step(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn interpret_cycle<D, I, S, R>(&mut self, ctx: &mut Context<D, I, S, R>) -> Result<(), VmError>
     where
         D: Display,
         I: Input,
         S: Sound,
+        R: Random,
     {
         use Instruction::*;
-        let inst = {
-            let pc = self.reg_pc as usize;
-            let ram = self.ram();
-            [ram[pc], ram[pc+1]]
+
+        // instructions have to end before the last byte of RAM,
+        // or moving past them would overflow the PC
+        let pc = self.reg_pc;
+        if pc as usize + 2 >= RAM_SIZE {
+            return Err(VmError::PcOutOfBounds { pc })
+        }
+        let inst = parser::read(&self.ram()[pc as usize..]);
+        if pc as usize + inst.size() as usize >= RAM_SIZE {
+            return Err(VmError::PcOutOfBounds { pc })
+        }
+
+        let opcode = u16::from_be_bytes([self.ram()[pc as usize], self.ram()[pc as usize + 1]]);
+        let check_range = |addr: u16, len: usize| {
+            if addr as usize + len > RAM_SIZE {
+                Err(VmError::MemoryOutOfBounds { pc, opcode, addr, len })
+            } else {
+                Ok(addr as usize)
+            }
+        };
+        let key = |k: u8| {
+            input::Key::try_from(k)
+                .map_err(|_| VmError::InvalidKey { pc, opcode, key: k })
         };
-        match parser::read(inst) {
+
+        match inst {
             UNKNOWN(_) => self.reg_pc += 2,
             CLS => {
-                ctx.clear();
+                self.fb.clear(self.planes);
+                self.dirty = true;
                 self.reg_pc += 2;
             },
             RET => {
+                if self.reg_sp == 0 {
+                    return Err(VmError::StackUnderflow { pc, opcode })
+                }
                 self.reg_sp -= 1;
                 let sp = self.reg_sp as usize;
                 self.reg_pc = self.stack()[sp];
@@ -122,41 +383,33 @@ impl VM {
             JPA(addr) => self.reg_pc = addr,
             CALL(addr) => {
                 let sp = self.reg_sp as usize;
+                if sp >= self.stack().len() {
+                    return Err(VmError::StackOverflow { pc, opcode })
+                }
                 self.stack_mut()[sp] = self.reg_pc + 2;
                 self.reg_sp += 1;
                 self.reg_pc = addr;
             },
             SEI(reg, val) => {
                 let reg = self.registers()[reg as usize];
-                self.reg_pc += if reg == val {
-                    4
-                } else {
-                    2
-                };
+                self.skip_if(reg == val);
             },
             SNEI(reg, val) => {
                 let reg = self.registers()[reg as usize];
-                self.reg_pc += if reg == val {
-                    2
-                } else {
-                    4
-                };
+                self.skip_if(reg != val);
             },
             SER(x, y) => {
                 let x = self.registers()[x as usize];
                 let y = self.registers()[y as usize];
-                self.reg_pc += if x == y {
-                    4
-                } else {
-                    2
-                };
+                self.skip_if(x == y);
             },
             LDI(reg, val) => {
                 self.registers_mut()[reg as usize] = val;
                 self.reg_pc += 2;
             },
             ADDI(reg, val) => {
-                self.registers_mut()[reg as usize] += val;
+                let x = self.registers()[reg as usize];
+                self.registers_mut()[reg as usize] = x.wrapping_add(val);
                 self.reg_pc += 2;
             },
             LDR(x, y) => {
@@ -167,16 +420,25 @@ impl VM {
             ORR(x, y) => {
                 let y = self.registers()[y as usize];
                 self.registers_mut()[x as usize] |= y;
+                if self.quirks.logic_resets_vf {
+                    self.registers_mut()[0xf] = 0;
+                }
                 self.reg_pc += 2;
             },
             ANDR(x, y) => {
                 let y = self.registers()[y as usize];
                 self.registers_mut()[x as usize] &= y;
+                if self.quirks.logic_resets_vf {
+                    self.registers_mut()[0xf] = 0;
+                }
                 self.reg_pc += 2;
             },
             XORR(x, y) => {
                 let y = self.registers()[y as usize];
                 self.registers_mut()[x as usize] ^= y;
+                if self.quirks.logic_resets_vf {
+                    self.registers_mut()[0xf] = 0;
+                }
                 self.reg_pc += 2;
             },
             ADDR(xx, y) => {
@@ -196,137 +458,280 @@ impl VM {
                 let x = self.registers()[xx as usize];
                 let y = self.registers()[y as usize];
 
-                self.registers_mut()[0xf] = if x > y {
-                    1
-                } else {
-                    0
-                };
+                // VF is set to 1 when there's no borrow
+                let no_borrow = (x >= y) as u8;
 
-                self.registers_mut()[xx as usize] -= y;
+                self.registers_mut()[xx as usize] = x.wrapping_sub(y);
+                self.registers_mut()[0xf] = no_borrow;
                 self.reg_pc += 2;
             },
-            SHRR(xx, _) => {
-                let x = self.registers()[xx as usize];
-                self.registers_mut()[0xf] = x & 1;
+            SHRR(xx, y) => {
+                let x = if self.quirks.shift_uses_vy {
+                    self.registers()[y as usize]
+                } else {
+                    self.registers()[xx as usize]
+                };
                 self.registers_mut()[xx as usize] = x >> 1;
+                self.registers_mut()[0xf] = x & 1;
                 self.reg_pc += 2;
             },
             SUBNR(xx, y) => {
                 let x = self.registers()[xx as usize];
                 let y = self.registers()[y as usize];
 
-                self.registers_mut()[0xf] = if y > x {
-                    1
-                } else {
-                    0
-                };
+                let no_borrow = (y >= x) as u8;
 
-                self.registers_mut()[xx as usize] = y - x;
+                self.registers_mut()[xx as usize] = y.wrapping_sub(x);
+                self.registers_mut()[0xf] = no_borrow;
                 self.reg_pc += 2;
             },
-            SHLR(xx, _) => {
-                let x = self.registers()[xx as usize];
-                self.registers_mut()[0xf] = x & 0x80;
+            SHLR(xx, y) => {
+                let x = if self.quirks.shift_uses_vy {
+                    self.registers()[y as usize]
+                } else {
+                    self.registers()[xx as usize]
+                };
                 self.registers_mut()[xx as usize] = x << 1;
+                self.registers_mut()[0xf] = x >> 7;
                 self.reg_pc += 2;
             },
             SNER(x, y) => {
                 let x = self.registers()[x as usize];
                 let y = self.registers()[y as usize];
-                self.reg_pc += if x == y {
-                    2
-                } else {
-                    4
-                };
+                self.skip_if(x != y);
             },
             LDA(addr) => {
                 self.reg_i = addr;
                 self.reg_pc += 2;
             },
-            JPAFAR(addr) => self.reg_pc = self.registers()[0] as u16 + addr,
+            JPAFAR(addr) => {
+                let reg = if self.quirks.jump_uses_vx {
+                    ((addr & 0x0f00) >> 8) as usize
+                } else {
+                    0
+                };
+                self.reg_pc = self.registers()[reg] as u16 + addr;
+            },
             RND(reg, val) => {
-                self.registers_mut()[reg as usize] = rand::byte() & val;
+                self.registers_mut()[reg as usize] = ctx.byte() & val;
                 self.reg_pc += 2;
             },
             DRW(x, y, n) => {
                 let x = self.registers()[x as usize] as usize;
                 let y = self.registers()[y as usize] as usize;
 
-                // read N bytes from memory starting at I
-                let i = self.reg_i as usize;
-                let n = n as usize;
-                let sprite = &self.ram()[i..i+n];
+                // read N bytes from memory starting at I, or
+                // a 16x16 sprite for DXY0, for each selected plane
+                let (width, n) = match n {
+                    0 => (16, 32),
+                    n => (8, n as usize),
+                };
+                let n = n * self.planes.count_ones() as usize;
+                let i = check_range(self.reg_i, n)?;
+                let mut sprite = [0; 32 * PLANES];
+                sprite[..n].copy_from_slice(&self.ram()[i..i+n]);
 
                 // draw the sprite onto the screen, and check collisions
-                self.registers_mut()[0xf] = ctx.draw(x, y, sprite) as u8;
+                let clip = self.quirks.clip_sprites;
+                let collision = self.fb.draw(x, y, width, &sprite[..n], self.planes, clip);
+                self.registers_mut()[0xf] = collision as u8;
+                self.dirty = true;
+                self.vblank_wait = self.quirks.display_wait;
                 self.reg_pc += 2;
             },
             SKP(reg) => {
                 let k = self.registers()[reg as usize];
-                let k = input::Key::try_from(k).unwrap();
+                let k = key(k)?;
                 let keys = ctx.poll_keyboard();
-                self.reg_pc += if keys[k] {
-                    4
-                } else {
-                    2
-                };
+                self.skip_if(keys[k]);
             },
             SKNP(reg) => {
                 let k = self.registers()[reg as usize];
-                let k = input::Key::try_from(k).unwrap();
+                let k = key(k)?;
                 let keys = ctx.poll_keyboard();
-                self.reg_pc += if keys[k] {
-                    2
-                } else {
-                    4
-                };
+                self.skip_if(!keys[k]);
             },
             LDTG(reg) => {
                 self.registers_mut()[reg as usize] = self.reg_dt;
                 self.reg_pc += 2;
             },
             LDK(reg) => {
-                self.registers_mut()[reg as usize] = ctx.wait_key() as u8;
-                self.reg_pc += 2;
+                // like the VIP, waits for a key to be pressed and
+                // released; the PC stays here until then, so frames
+                // go on, with the timers, while it waits
+                let keys = ctx.poll_keyboard();
+                match self.key_down {
+                    Some(k) if !keys[k] => {
+                        self.key_down = None;
+                        self.registers_mut()[reg as usize] = k as u8;
+                        self.reg_pc += 2;
+                    },
+                    Some(_) => (),
+                    None => self.key_down = keys.first(),
+                }
             },
             LDTS(reg) => {
                 self.reg_dt = self.registers()[reg as usize];
                 self.reg_pc += 2;
             },
             LDSS(reg) => {
-                ctx.beep_start();
-                self.reg_snd = self.registers()[reg as usize];
+                // writing 0 silences the beep instead of starting one
+                let x = self.registers()[reg as usize];
+                if x > 0 {
+                    ctx.beep_start();
+                } else if self.reg_snd > 0 {
+                    ctx.beep_end();
+                }
+                self.reg_snd = x;
                 self.reg_pc += 2;
             },
             ADDA(reg) => {
-                self.reg_i += self.registers()[reg as usize] as u16;
+                let x = self.registers()[reg as usize] as u16;
+                self.reg_i = self.reg_i.wrapping_add(x);
                 self.reg_pc += 2;
             },
-            LDDIG(dig) => {
+            LDDIG(reg) => {
+                let dig = self.registers()[reg as usize] & 0xf;
                 self.reg_i = (dig as u16) * 5_u16;
                 self.reg_pc += 2;
             },
-            LDBCD(_register) => {
-                unimplemented!()
+            LDBCD(reg) => {
+                let x = self.registers()[reg as usize];
+                let i = check_range(self.reg_i, 3)?;
+                self.ram_mut()[i..i+3].copy_from_slice(&[x / 100, x / 10 % 10, x % 10]);
+                self.reg_pc += 2;
             },
             LDREGST(x) => {
-                let off = self.reg_i as usize;
                 let x = x as usize;
-                for i in 0..x {
-                    self.ram_mut()[off+i] = self.registers()[i];
-                }
+                let off = check_range(self.reg_i, x + 1)?;
+                let regs = *self.registers();
+                self.ram_mut()[off..=off+x].copy_from_slice(&regs[0..=x]);
+                self.advance_i(x);
+                self.reg_pc += 2;
             },
             LDREGRD(x) => {
-                let off = self.reg_i as usize;
                 let x = x as usize;
-                for i in 0..x {
-                    self.registers_mut()[i] = self.ram_mut()[off+i];
+                let off = check_range(self.reg_i, x + 1)?;
+                let mut regs = *self.registers();
+                regs[0..=x].copy_from_slice(&self.ram()[off..=off+x]);
+                *self.registers_mut() = regs;
+                self.advance_i(x);
+                self.reg_pc += 2;
+            },
+            SCD(n) => {
+                self.fb.scroll_down(self.planes, n as usize);
+                self.dirty = true;
+                self.reg_pc += 2;
+            },
+            SCR => {
+                self.fb.scroll_right(self.planes, 4);
+                self.dirty = true;
+                self.reg_pc += 2;
+            },
+            SCL => {
+                self.fb.scroll_left(self.planes, 4);
+                self.dirty = true;
+                self.reg_pc += 2;
+            },
+            EXIT => self.halted = true,
+            LOW => {
+                self.fb.set_hires(false);
+                self.dirty = true;
+                self.reg_pc += 2;
+            },
+            HIGH => {
+                self.fb.set_hires(true);
+                self.dirty = true;
+                self.reg_pc += 2;
+            },
+            LDHDIG(reg) => {
+                let dig = self.registers()[reg as usize] & 0xf;
+                self.reg_i = BIG_FONT_ADDR as u16 + (dig as u16) * 10_u16;
+                self.reg_pc += 2;
+            },
+            LDRPLST(x) => {
+                let x = x as usize;
+                let regs = *self.registers();
+                self.rpl[0..=x].copy_from_slice(&regs[0..=x]);
+                self.reg_pc += 2;
+            },
+            LDRPLRD(x) => {
+                let x = x as usize;
+                let rpl = self.rpl;
+                self.registers_mut()[0..=x].copy_from_slice(&rpl[0..=x]);
+                self.reg_pc += 2;
+            },
+            LDLONG(addr) => {
+                self.reg_i = addr;
+                self.reg_pc += 4;
+            },
+            LDRNGST(x, y) => {
+                let i = check_range(self.reg_i, register_range(x, y).count())?;
+                let regs = *self.registers();
+                for (k, r) in register_range(x, y).enumerate() {
+                    self.ram_mut()[i+k] = regs[r];
                 }
+                self.reg_pc += 2;
+            },
+            LDRNGRD(x, y) => {
+                let i = check_range(self.reg_i, register_range(x, y).count())?;
+                for (k, r) in register_range(x, y).enumerate() {
+                    self.registers_mut()[r] = self.ram()[i+k];
+                }
+                self.reg_pc += 2;
+            },
+            PLANE(mask) => {
+                self.planes = mask & ((1 << PLANES) - 1);
+                self.reg_pc += 2;
+            },
+            SCU(n) => {
+                self.fb.scroll_up(self.planes, n as usize);
+                self.dirty = true;
+                self.reg_pc += 2;
             },
+            AUDIO => {
+                let i = check_range(self.reg_i, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.ram()[i..i+16]);
+                self.pattern = pattern;
+                ctx.set_pattern(&self.pattern);
+                self.reg_pc += 2;
+            },
+            PITCH(reg) => {
+                self.pitch = self.registers()[reg as usize];
+                ctx.set_pitch(self.pitch);
+                self.reg_pc += 2;
+            },
+        }
+
+        Ok(())
+    }
+
+    // skips over the next instruction, which may be F000 NNNN
+    fn skip_if(&mut self, cond: bool) {
+        self.reg_pc += 2;
+        if cond {
+            let size = self.current_instruction().size();
+            self.reg_pc = self.reg_pc.wrapping_add(size);
+        }
+    }
+
+    fn advance_i(&mut self, x: usize) {
+        match self.quirks.load_store {
+            LoadStore::Increment => self.reg_i = self.reg_i.wrapping_add(x as u16 + 1),
+            LoadStore::IncrementByX => self.reg_i = self.reg_i.wrapping_add(x as u16),
+            LoadStore::Unchanged => (),
         }
     }
 }
 
+// XO-CHIP 5XY2/5XY3 go through the registers in either direction
+fn register_range(x: Register, y: Register) -> impl Iterator<Item = usize> {
+    let (x, y) = (x as usize, y as usize);
+    let n = y.abs_diff(x);
+    (0..=n).map(move |k| if x <= y { x + k } else { x - k })
+}
+
 static FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70,
     0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0,
@@ -338,8 +743,193 @@ static FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
 
-pub const CPU_FREQ: u64 = 500;
-pub const DELAY_TICK_FREQ: u64 = 60;
-pub const CPU_DELAY: Duration = Duration::from_millis(1000 / CPU_FREQ);
+// SUPER-CHIP 8x10 digits, placed right after the small font
+const BIG_FONT_ADDR: usize = 0x50;
+
+static BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C,
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3,
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC,
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C,
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+// XO-CHIP extends the address space to 64 KiB
+pub const RAM_SIZE: usize = 0x10000;
+
+// where programs are loaded unless told otherwise, after the 512
+// bytes the COSMAC VIP's interpreter took up
+pub const PROGRAM_START: u16 = 0x200;
+
+pub const CPU_FREQ: u32 = 500;
+pub const DELAY_TICK_FREQ: u32 = 60;
+
+// the default speed, about CPU_FREQ instructions per second
+pub const CYCLES_PER_FRAME: u32 = CPU_FREQ / DELAY_TICK_FREQ;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vm(rom: &[u8]) -> VM {
+        let mut vm = VM::new(Quirks::default());
+        vm.load(rom).unwrap();
+        vm
+    }
+
+    // LD V0, 0xFF; LD DT, V0; then counts up in V1 forever
+    const COUNT: [u8; 8] = [0x60, 0xff, 0xf0, 0x15, 0x71, 0x01, 0x12, 0x04];
+
+    #[test]
+    fn run_cycles_stops_on_exit() {
+        // EXIT
+        let mut vm = vm(&[0x00, 0xfd]);
+        let mut ctx = Context::new((), (), ());
+        vm.run_cycles(&mut ctx, 100).unwrap();
+        assert!(vm.halted());
+        assert_eq!(vm.pc(), 0x200);
+    }
+
+    #[test]
+    fn run_frame_ticks_the_timers_once() {
+        let mut vm = vm(&COUNT);
+        let mut ctx = Context::new((), (), ());
+        vm.run_until(&mut ctx, |vm| vm.dt() != 0).unwrap();
+        let dt = vm.dt();
+        vm.run_frame(&mut ctx).unwrap();
+        assert_eq!(vm.dt(), dt - 1);
+        vm.set_cycles_per_frame(100);
+        let count = vm.registers()[1];
+        vm.run_frame(&mut ctx).unwrap();
+        assert_eq!(vm.dt(), dt - 2);
+        assert_eq!(vm.registers()[1], count + 50);
+    }
+
+    #[test]
+    fn power_on_and_resets() {
+        let mut vm = VM::with_power_on(Quirks::default(), PowerOnPattern::Vip);
+        assert_eq!(&vm.ram()[0x300..0x304], &[0x00, 0xff, 0x00, 0xff]);
+        assert_eq!(vm.registers()[1], 0xff);
+        assert_eq!(&vm.ram()[..5], &FONT[..5]);
+
+        // LD V0, 0x42; LD I, 0x300; LD [I], V0; JP 0x206
+        vm.load([0x60, 0x42, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x06]).unwrap();
+        vm.run_cycles(&mut Context::new((), (), ()), 10).unwrap();
+        assert_eq!(vm.ram()[0x300], 0x42);
+
+        // memory survives a soft reset
+        vm.reset();
+        assert_eq!((vm.pc(), vm.i()), (0x200, 0));
+        assert_eq!(vm.registers(), &[0; 16]);
+        assert_eq!(vm.ram()[0x300], 0x42);
+        assert_eq!(vm.ram()[0x200], 0x60);
+
+        // but not a hard one
+        vm.hard_reset();
+        assert_eq!(&vm.ram()[0x200..0x202], &[0x00, 0xff]);
+        assert_eq!(vm.ram()[0x300], 0x00);
+        assert_eq!(&vm.ram()[..5], &FONT[..5]);
+    }
+
+    #[test]
+    fn loading_elsewhere() {
+        // LD V0, 1; JP 0x602
+        let mut vm = VM::new(Quirks::default());
+        vm.load_at(0x600, [0x60, 0x01, 0x16, 0x02]).unwrap();
+        assert_eq!(vm.pc(), 0x600);
+        vm.run_cycles(&mut Context::new((), (), ()), 3).unwrap();
+        assert_eq!((vm.pc(), vm.registers()[0]), (0x602, 1));
+
+        // a reset goes back to where the program starts
+        vm.reset();
+        assert_eq!(vm.pc(), 0x600);
 
-const DECREMENT: i32 = (CPU_FREQ / DELAY_TICK_FREQ) as i32;
+        let max = RAM_SIZE - 0xff00;
+        assert_eq!(
+            vm.load_at(0xff00, vec![0; max + 1]),
+            Err(VmError::RomTooLarge { size: max + 1, max })
+        );
+    }
+
+    // counts beeps started and ended
+    #[derive(Default)]
+    struct Beeps(u32, u32);
+
+    impl Sound for Beeps {
+        fn beep_start(&mut self) {
+            self.0 += 1;
+        }
+
+        fn beep_end(&mut self) {
+            self.1 += 1;
+        }
+    }
+
+    #[test]
+    fn writing_zero_to_the_sound_timer() {
+        // LD V0, 0; LD ST, V0; LD V0, 9; LD ST, V0; LD V1, 0; LD ST, V1
+        let mut vm = vm(&[0x60, 0x00, 0xf0, 0x18, 0x60, 0x09, 0xf0, 0x18, 0x61, 0x00, 0xf1, 0x18]);
+        let mut ctx = Context::new((), (), Beeps::default());
+        vm.run_cycles(&mut ctx, 2).unwrap();
+        assert_eq!((ctx.sound_mut().0, ctx.sound_mut().1), (0, 0));
+        vm.run_cycles(&mut ctx, 2).unwrap();
+        assert_eq!((ctx.sound_mut().0, ctx.sound_mut().1), (1, 0));
+        vm.run_cycles(&mut ctx, 2).unwrap();
+        assert_eq!((ctx.sound_mut().0, ctx.sound_mut().1), (1, 1));
+        assert_eq!(vm.st(), 0);
+    }
+
+    #[test]
+    fn display_wait_ends_the_frame() {
+        // LD V0, 5; DRW V0, V0, 1; ADD V0, 1; JP 0x202
+        let rom = [0x60, 0x05, 0xd0, 0x01, 0x70, 0x01, 0x12, 0x02];
+        let mut ctx = Context::new((), (), ());
+
+        let mut vm = vm(&rom);
+        vm.run_frame(&mut ctx).unwrap();
+        assert_eq!(vm.pc(), 0x204);
+        vm.run_frame(&mut ctx).unwrap();
+        assert_eq!(vm.pc(), 0x204);
+        assert_eq!(vm.registers()[0], 6);
+
+        let mut vm = VM::new(Quirks { display_wait: false, ..Quirks::VIP });
+        vm.load(rom).unwrap();
+        vm.run_frame(&mut ctx).unwrap();
+        assert_eq!(vm.registers()[0], 5 + 2);
+    }
+
+    #[test]
+    fn run_until_checks_before_each_step() {
+        let mut vm = vm(&COUNT);
+        let mut ctx = Context::new((), (), ());
+        vm.run_until(&mut ctx, |vm| vm.registers()[1] == 10).unwrap();
+        assert_eq!(vm.registers()[1], 10);
+        assert_eq!(vm.pc(), 0x206);
+
+        vm.run_until(&mut ctx, |_| true).unwrap();
+        assert_eq!(vm.pc(), 0x206);
+    }
+
+    #[test]
+    fn random_bytes_come_from_the_context() {
+        use drivers::random::Replay;
+
+        // RND V0, 0x0F; RND V1, 0xFF
+        let mut vm = vm(&[0xc0, 0x0f, 0xc1, 0xff]);
+        let mut ctx = Context::with_random((), (), (), Replay::new([0xab, 0xcd]));
+        vm.run_cycles(&mut ctx, 2).unwrap();
+        assert_eq!(vm.registers()[0], 0x0b);
+        assert_eq!(vm.registers()[1], 0xcd);
+    }
+}
@@ -1,49 +1,77 @@
 pub mod display;
 pub mod input;
 pub mod sound;
+pub mod random;
 
 pub use display::Display;
 pub use input::Input;
 pub use sound::Sound;
+pub use random::Random;
 
-pub struct Context<D, I, S> {
+use random::Xorshift;
+use super::framebuffer::Framebuffer;
+
+pub struct Context<D, I, S, R = Xorshift> {
     display: D,
     input: I,
     sound: S,
+    random: R,
 }
 
+#[cfg(feature = "std")]
 impl<D, I, S> Context<D, I, S>
 where
     D: Display,
     I: Input,
     S: Sound,
 {
+    // random bytes come from a generator seeded with the clock
     pub fn new(display: D, input: I, sound: S) -> Self {
-        Context { display, input, sound }
+        Self::with_random(display, input, sound, Xorshift::from_clock())
     }
 }
 
-impl<D: Display, I, S> Display for Context<D, I, S> {
-    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        self.display.draw(x, y, sprite)
+impl<D, I, S, R> Context<D, I, S, R>
+where
+    D: Display,
+    I: Input,
+    S: Sound,
+    R: Random,
+{
+    pub fn with_random(display: D, input: I, sound: S, random: R) -> Self {
+        Context { display, input, sound, random }
     }
 
-    fn clear(&mut self) {
-        self.display.clear()
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn sound_mut(&mut self) -> &mut S {
+        &mut self.sound
     }
 }
 
-impl<D, I: Input, S> Input for Context<D, I, S> {
-    fn poll_keyboard(&mut self) -> input::KeySet {
-        self.input.poll_keyboard()
+impl<D: Display, I, S, R> Display for Context<D, I, S, R> {
+    fn present(&mut self, fb: &Framebuffer) {
+        self.display.present(fb)
+    }
+
+    fn flush(&mut self) {
+        self.display.flush()
     }
+}
 
-    fn wait_key(&mut self) -> input::Key {
-        self.input.wait_key()
+impl<D, I: Input, S, R> Input for Context<D, I, S, R> {
+    fn poll_keyboard(&mut self) -> input::KeySet {
+        self.input.poll_keyboard()
     }
 }
 
-impl<D, I, S: Sound> Sound for Context<D, I, S> {
+impl<D, I, S: Sound, R> Sound for Context<D, I, S, R> {
     fn beep_start(&mut self) {
         self.sound.beep_start()
     }
@@ -51,4 +79,22 @@ impl<D, I, S: Sound> Sound for Context<D, I, S> {
     fn beep_end(&mut self) {
         self.sound.beep_end()
     }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16]) {
+        self.sound.set_pattern(pattern)
+    }
+
+    fn set_pitch(&mut self, pitch: u8) {
+        self.sound.set_pitch(pitch)
+    }
+
+    fn end_frame(&mut self) {
+        self.sound.end_frame()
+    }
+}
+
+impl<D, I, S, R: Random> Random for Context<D, I, S, R> {
+    fn byte(&mut self) -> u8 {
+        self.random.byte()
+    }
 }
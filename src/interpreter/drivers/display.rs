@@ -1,74 +1,47 @@
-pub const DISPLAY_WIDTH: usize = 64;
-pub const DISPLAY_HEIGHT: usize = 32;
-pub const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+use crate::interpreter::framebuffer::Framebuffer;
+
+pub mod palette;
+pub mod phosphor;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod sixel;
+#[cfg(feature = "std")]
+pub mod kitty;
+#[cfg(feature = "std")]
+pub mod zlib;
+#[cfg(feature = "std")]
+pub mod probe;
+#[cfg(feature = "std")]
+pub mod terminal;
+
+#[cfg(feature = "std")]
+pub use terminal::{TerminalDisplay, detect};
 
 pub trait Display {
-    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool;
-    fn clear(&mut self);
-}
+    // shows the screen; called at the end of every frame in which it
+    // changed, and when the program exits
+    fn present(&mut self, fb: &Framebuffer);
 
-pub struct TerminalDisplay {
-    buf: String,
-    scr: [u8; DISPLAY_SIZE],
+    // displays that hold back frames to limit their frame rate should
+    // show the last one now; called once the frames due have run
+    fn flush(&mut self) {}
 }
 
 impl Display for () {
-    fn draw(&mut self, _x: usize, _y: usize, _sprite: &[u8]) -> bool {
-        false
-    }
-
-    fn clear(&mut self) {}
-}
-
-impl TerminalDisplay {
-    pub const fn new() -> Self {
-        let buf = String::new();
-        let scr = [0_u8; DISPLAY_SIZE];
-        TerminalDisplay { buf, scr }
-    }
+    fn present(&mut self, _fb: &Framebuffer) {}
 }
 
-impl Display for TerminalDisplay {
-    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        let mut collision = false;
-        let n = sprite.len();
-
-        for j in 0..n {
-            for i in 0..8 {
-                let yj = (y + j) % DISPLAY_HEIGHT;
-                let xi = (x + i) % DISPLAY_WIDTH;
-
-                if (sprite[j] & (0x80 >> i)) != 0 {
-                    if self.scr[yj*DISPLAY_WIDTH + xi] == 1 {
-                        collision = true;
-                    }
-                    self.scr[yj*DISPLAY_WIDTH + xi] ^= 1;
-                }
-            }
-        }
-
-
-        for y in 0..DISPLAY_HEIGHT {
-            self.buf.clear();
-            self.buf.push_str(termion::color::LightGreen.fg_str());
-            for x in 0..DISPLAY_WIDTH {
-                if self.scr[y*DISPLAY_WIDTH + x] == 0 {
-                    self.buf.push(' ')
-                } else {
-                    self.buf.push('█')
-                }
-            }
-            self.buf.push_str(termion::color::Reset.fg_str());
-            println!("{}{}", termion::cursor::Goto(0, y as u16), &self.buf)
-        }
-
-        collision
+#[cfg(feature = "std")]
+impl<D: Display + ?Sized> Display for Box<D> {
+    fn present(&mut self, fb: &Framebuffer) {
+        (**self).present(fb)
     }
 
-    fn clear(&mut self) {
-        for x in self.scr.iter_mut() {
-            *x = 0;
-        }
-        print!("{}", termion::clear::All);
+    fn flush(&mut self) {
+        (**self).flush()
     }
 }
+
+// how many terminal pixels the graphics displays draw per pixel
+pub const DEFAULT_SCALE: u8 = 4;
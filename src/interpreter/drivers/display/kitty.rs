@@ -0,0 +1,242 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::interpreter::framebuffer::Framebuffer;
+use super::palette::Palette;
+use super::zlib;
+use super::Display;
+use crate::interpreter::scheduler::{Clock, StdClock};
+use super::terminal::{Pacer, enter_screen, leave_screen};
+
+// the protocol's limit on the base64 data in each escape sequence
+const CHUNK_SIZE: usize = 4096;
+
+// every frame replaces the same image and placement, so
+// the terminal doesn't keep the old ones around
+const IMAGE_ID: u32 = 1;
+
+// draws the screen as an image with the kitty graphics protocol, for
+// kitty, WezTerm, Ghostty and Konsole
+pub struct KittyDisplay<W: Write = io::Stdout, C: Clock = StdClock> {
+    out: W,
+    buf: String,
+    scale: usize,
+    palette: Palette,
+    pacer: Pacer<C>,
+    rgb: Vec<u8>,
+}
+
+impl KittyDisplay {
+    pub fn new(scale: u8, palette: Palette) -> Self {
+        Self::with_output(io::stdout(), scale, palette)
+    }
+}
+
+impl<W: Write> KittyDisplay<W> {
+    pub fn with_output(out: W, scale: u8, palette: Palette) -> Self {
+        Self::with_clock(out, scale, palette, StdClock::new())
+    }
+}
+
+impl<W: Write, C: Clock> KittyDisplay<W, C> {
+    // paces redraws by `clock` instead of the host's
+    pub fn with_clock(mut out: W, scale: u8, palette: Palette, clock: C) -> Self {
+        enter_screen(&mut out);
+
+        KittyDisplay {
+            out,
+            buf: String::new(),
+            scale: scale.max(1) as usize,
+            palette,
+            pacer: Pacer::with_clock(clock),
+            rgb: Vec::new(),
+        }
+    }
+
+    // pixels that turn off fade out over this many redraws
+    pub fn set_fade(&mut self, steps: u8) {
+        self.pacer.fade = steps;
+    }
+
+    fn redraw(&mut self) {
+        self.buf.clear();
+        encode(&self.pacer.frame, self.scale, &self.palette, self.pacer.fade, &mut self.rgb, &mut self.buf);
+
+        let _ = write!(self.out, "{}", termion::cursor::Goto(1, 1));
+        let _ = self.out.write_all(self.buf.as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+// writes the framebuffer as a 24-bit RGB image, scaling each pixel
+// to scale x scale, compressed with zlib (o=z) and split into as many
+// escape sequences as it takes
+pub fn encode(fb: &Framebuffer, scale: usize, palette: &Palette, fade: u8, rgb: &mut Vec<u8>, out: &mut String) {
+    let (w, h) = (fb.width() * scale, fb.height() * scale);
+    rgb.clear();
+    for y in 0..h {
+        for x in 0..w {
+            let c = palette.shade(fb.pixel(x / scale, y / scale), fade);
+            rgb.extend_from_slice(&[c.0, c.1, c.2]);
+        }
+    }
+
+    // responses are turned off with q=2, as they would
+    // reach the input driver as key presses
+    let data = base64(&zlib::compress(rgb));
+    let mut start = 0;
+    while start < data.len() {
+        let end = (start + CHUNK_SIZE).min(data.len());
+        out.push_str("\x1b_G");
+        if start == 0 {
+            let _ = write!(out, "a=T,f=24,o=z,s={},v={},i={},p=1,C=1,q=2,", w, h, IMAGE_ID);
+        }
+        // m=1 means more chunks follow
+        let _ = write!(out, "m={};{}\x1b\\", (end < data.len()) as u8, &data[start..end]);
+        start = end;
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl<W: Write, C: Clock> Display for KittyDisplay<W, C> {
+    fn present(&mut self, fb: &Framebuffer) {
+        if self.pacer.present(fb) {
+            self.redraw();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pacer.flush() {
+            self.redraw();
+        }
+    }
+}
+
+impl<W: Write, C: Clock> Drop for KittyDisplay<W, C> {
+    fn drop(&mut self) {
+        let _ = write!(self.out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", IMAGE_ID);
+        leave_screen(&mut self.out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::terminal::tests::{Shared, FakeClock};
+    use super::super::terminal::MIN_REDRAW_INTERVAL;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(&[0xff, 0xef]), "/+8=");
+    }
+
+    #[test]
+    fn compressed() {
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+
+        let mut rgb = Vec::new();
+        let mut out = String::new();
+        encode(&fb, 1, &Palette::default(), 0, &mut rgb, &mut out);
+        // a green pixel, then black
+        assert!(rgb.starts_with(&[0x55, 0xff, 0x55, 0, 0, 0]));
+
+        // 64x32 RGB is 6144 bytes, which fit in a single chunk
+        let (keys, data) = out.split_once(';').unwrap();
+        assert_eq!(keys, "\x1b_Ga=T,f=24,o=z,s=64,v=32,i=1,p=1,C=1,q=2,m=0");
+        assert!(data.ends_with("\x1b\\"));
+        assert_eq!(data.trim_end_matches("\x1b\\"), base64(&zlib::compress(&rgb)));
+    }
+
+    #[test]
+    fn chunks() {
+        // noise compresses poorly enough to need more than one
+        let mut fb = Framebuffer::new();
+        fb.set_hires(true);
+        let mut x = 1_u32;
+        for px in fb.pixels.iter_mut() {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            *px = (x & 3) as u8;
+        }
+
+        let mut out = String::new();
+        encode(&fb, 1, &Palette::default(), 0, &mut Vec::new(), &mut out);
+        let chunks: Vec<&str> = out.split_terminator("\x1b\\").collect();
+        assert!(chunks.len() > 1);
+        let (keys, data) = chunks[0].split_once(';').unwrap();
+        assert!(keys.ends_with(",m=1"));
+        assert_eq!(data.len(), CHUNK_SIZE);
+        assert!(chunks[1..].iter().all(|c| c.starts_with("\x1b_Gm=")));
+        assert!(chunks.last().unwrap().starts_with("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn scaling() {
+        let mut rgb = Vec::new();
+        encode(&Framebuffer::new(), 3, &Palette::default(), 0, &mut rgb, &mut String::new());
+        assert_eq!(rgb.len(), 192 * 96 * 3);
+    }
+
+    #[test]
+    fn replaces_the_image() {
+        let out = Shared::default();
+        let mut disp = KittyDisplay::with_output(out.clone(), 1, Palette::default());
+        out.take();
+
+        disp.present(&Framebuffer::new());
+        assert!(out.take().starts_with("\x1b[1;1H\x1b_Ga=T,"));
+
+        drop(disp);
+        assert!(out.take().starts_with("\x1b_Ga=d,d=I,i=1,q=2\x1b\\\x1b[?1049l"));
+    }
+
+    #[test]
+    fn redraws_at_most_once_a_frame() {
+        let out = Shared::default();
+        let clock = FakeClock::default();
+        let mut disp = KittyDisplay::with_clock(out.clone(), 1, Palette::default(), clock.clone());
+        out.take();
+
+        let mut fb = Framebuffer::new();
+        disp.present(&fb);
+        assert!(out.take().contains("\x1b_Ga=T,"));
+
+        // too soon for another one, until flushed
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+        clock.advance(MIN_REDRAW_INTERVAL / 2);
+        disp.present(&fb);
+        assert_eq!(out.take(), "");
+        disp.flush();
+        assert!(out.take().contains("\x1b_Ga=T,"));
+
+        // a frame later it is drawn right away
+        fb.draw(8, 0, 8, &[0x80], 1, false);
+        clock.advance(MIN_REDRAW_INTERVAL);
+        disp.present(&fb);
+        assert!(out.take().contains("\x1b_Ga=T,"));
+        disp.flush();
+        assert_eq!(out.take(), "");
+    }
+}
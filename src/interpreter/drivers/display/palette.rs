@@ -0,0 +1,118 @@
+// a colour, as red, green and blue
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    // parses RRGGBB in hex, with or without a leading #
+    pub fn parse(s: &str) -> Option<Rgb> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+        if s.len() != 6 || !s.is_ascii() {
+            return None
+        }
+        let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    // `self` moved `amount` of the way towards `other`, out of `of`
+    fn towards(self, other: Rgb, amount: u32, of: u32) -> Rgb {
+        let mix = |a: u8, b: u8| {
+            let (a, b) = (a as i32, b as i32);
+            (a + (b - a) * amount as i32 / of as i32) as u8
+        };
+        Rgb(mix(self.0, other.0), mix(self.1, other.1), mix(self.2, other.2))
+    }
+}
+
+// the colour of each pixel value: off, the first plane,
+// the second plane, and both planes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Palette(pub [Rgb; 4]);
+
+pub const THEMES: [&str; 4] = ["default", "vip", "lcd", "amber"];
+
+impl Palette {
+    // a foreground on a background, with the default
+    // colours for the second plane and both planes
+    pub fn with_colors(fg: Rgb, bg: Rgb) -> Self {
+        let Palette([_, _, two, both]) = Palette::default();
+        Palette([bg, fg, two, both])
+    }
+
+    pub fn theme(name: &str) -> Option<Palette> {
+        let rgb = |c: u32| Rgb((c >> 16) as u8, (c >> 8) as u8, c as u8);
+        let name = THEMES.iter().find(|theme| theme.eq_ignore_ascii_case(name))?;
+        let colors = match *name {
+            "default" => return Some(Palette::default()),
+            // the COSMAC VIP's white on black
+            "vip" => [0x000000, 0xffffff, 0x808080, 0xc0c0c0],
+            // dark pixels on a green liquid crystal display
+            "lcd" => [0x9bbc0f, 0x0f380f, 0x6b8c0f, 0x306230],
+            // an amber monochrome monitor
+            "amber" => [0x140c00, 0xffb000, 0x8c5a00, 0xffd87a],
+            _ => return None,
+        };
+        Some(Palette(colors.map(rgb)))
+    }
+
+    // a theme's name, or a foreground and background as FG,BG
+    pub fn parse(s: &str) -> Option<Palette> {
+        match s.split_once(',') {
+            Some((fg, bg)) => Some(Palette::with_colors(Rgb::parse(fg)?, Rgb::parse(bg)?)),
+            None => Palette::theme(s),
+        }
+    }
+
+    pub fn color(&self, px: u8) -> Rgb {
+        self.0[px as usize & 3]
+    }
+
+    // the colour of a pixel value that may be fading out,
+    // as phosphor::decay leaves them, over `steps` steps
+    pub fn shade(&self, px: u8, steps: u8) -> Rgb {
+        let step = super::phosphor::step(px) as u32;
+        self.color(px).towards(self.0[0], step.min(steps as u32 + 1), steps as u32 + 1)
+    }
+}
+
+// the same colours as the terminal display's light green, red and yellow
+impl Default for Palette {
+    fn default() -> Self {
+        Palette([
+            Rgb(0x00, 0x00, 0x00),
+            Rgb(0x55, 0xff, 0x55),
+            Rgb(0xff, 0x55, 0x55),
+            Rgb(0xff, 0xff, 0x55),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Rgb::parse("#ffb000"), Some(Rgb(0xff, 0xb0, 0x00)));
+        assert_eq!(Rgb::parse("0A0b0C"), Some(Rgb(10, 11, 12)));
+        assert_eq!(Rgb::parse("fff"), None);
+        assert_eq!(Rgb::parse("gg0000"), None);
+
+        for name in THEMES.iter() {
+            assert!(Palette::parse(name).is_some(), "{}", name);
+        }
+        assert_eq!(Palette::parse("VIP").unwrap().color(1), Rgb(0xff, 0xff, 0xff));
+        let p = Palette::parse("#ffffff,#000080").unwrap();
+        assert_eq!((p.color(1), p.color(0)), (Rgb(0xff, 0xff, 0xff), Rgb(0, 0, 0x80)));
+        assert_eq!(Palette::parse("ffffff,blue"), None);
+        assert_eq!(Palette::parse("sepia"), None);
+    }
+
+    #[test]
+    fn shades() {
+        let p = Palette::with_colors(Rgb(200, 100, 0), Rgb(0, 0, 100));
+        assert_eq!(p.shade(1, 3), Rgb(200, 100, 0));
+        // a quarter of the way to the background at each step
+        assert_eq!(p.shade(1 | 1 << 2, 3), Rgb(150, 75, 25));
+        assert_eq!(p.shade(1 | 3 << 2, 3), Rgb(50, 25, 75));
+    }
+}
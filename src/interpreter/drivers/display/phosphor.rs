@@ -0,0 +1,69 @@
+// Phosphor persistence: pixels that turn off fade out over a few
+// redraws, which hides the flicker of sprites being erased and drawn
+// again. While a pixel fades, its value keeps the planes it was lit
+// in in the two low bits, and counts the steps since it turned off
+// above them; Palette::shade gives the colour for each step.
+
+use crate::interpreter::framebuffer::Framebuffer;
+
+// the most steps a pixel value can count
+pub const MAX_STEPS: u8 = 63;
+
+// how many steps the pixel has faded
+pub fn step(px: u8) -> u8 {
+    px >> 2
+}
+
+// takes `shown` one step further from the last frame shown to `fb`:
+// lit pixels are shown as they are, and the ones that are off but
+// were shown take one more step towards fading out after `steps`
+pub fn decay(shown: &mut Framebuffer, fb: &Framebuffer, steps: u8) {
+    if shown.hires != fb.hires || steps == 0 {
+        shown.clone_from(fb);
+        return
+    }
+
+    let steps = steps.min(MAX_STEPS);
+    for (old, &new) in shown.pixels.iter_mut().zip(fb.pixels.iter()) {
+        *old = match (new, *old) {
+            (0, 0) => 0,
+            (0, old) if step(old) >= steps => 0,
+            (0, old) => (old & 3) | (step(old) + 1) << 2,
+            (new, _) => new,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_over_the_steps() {
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, &[0xc0], 2, false);
+        let mut shown = fb.clone();
+
+        // the sprite is erased, and fades out over two steps
+        fb.draw(0, 0, 8, &[0x80], 2, false);
+        decay(&mut shown, &fb, 2);
+        assert_eq!(&shown.pixels[..2], &[2 | 1 << 2, 2]);
+        decay(&mut shown, &fb, 2);
+        assert_eq!(&shown.pixels[..2], &[2 | 2 << 2, 2]);
+        decay(&mut shown, &fb, 2);
+        assert_eq!(shown, fb);
+
+        // and is lit again straight away
+        fb.draw(0, 0, 8, &[0x80], 2, false);
+        decay(&mut shown, &fb, 2);
+        assert_eq!(shown, fb);
+    }
+
+    #[test]
+    fn no_steps_shows_the_frame() {
+        let mut shown = Framebuffer::new();
+        shown.pixels[5] = 1;
+        decay(&mut shown, &Framebuffer::new(), 0);
+        assert_eq!(shown, Framebuffer::new());
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use termion::raw::IntoRawMode;
+
+// what the terminal can draw, best first
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Support {
+    Kitty,
+    Sixel,
+    Cells,
+}
+
+// a kitty graphics query for a 1x1 image, which terminals that know
+// the protocol answer with OK, followed by a primary device attributes
+// request, which every terminal answers, listing 4 if it has sixels;
+// the second answer tells us when all of them are in
+pub const QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+
+const KITTY_OK: &[u8] = b"\x1b_Gi=31;OK\x1b\\";
+
+// asks the terminal what it supports, giving up on
+// it after the timeout if it doesn't answer
+pub fn probe(timeout: Duration) -> io::Result<Support> {
+    let mut tty = termion::get_tty()?;
+    // the answers would otherwise be echoed and wait for a newline
+    let _raw = tty.try_clone()?.into_raw_mode()?;
+    tty.write_all(QUERY.as_bytes())?;
+    tty.flush()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    loop {
+        if let Some(support) = parse_reply(&reply) {
+            return Ok(support)
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if !readable(&tty, left)? {
+            return Ok(Support::Cells)
+        }
+        let mut buf = [0; 64];
+        match tty.read(&mut buf)? {
+            0 => return Ok(Support::Cells),
+            n => reply.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+// waits for the terminal to send something
+fn readable(tty: &File, timeout: Duration) -> io::Result<bool> {
+    let mut fd = libc::pollfd { fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    match unsafe { libc::poll(&mut fd, 1, ms) } {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n > 0),
+    }
+}
+
+// works out what the terminal supports from its answers to QUERY,
+// or returns None if the device attributes haven't all arrived yet
+pub fn parse_reply(reply: &[u8]) -> Option<Support> {
+    const DA: &[u8] = b"\x1b[?";
+    let start = reply.windows(DA.len()).position(|w| w == DA)? + DA.len();
+    let len = reply[start..].iter().position(|&b| b == b'c')?;
+    let attrs = &reply[start..start + len];
+
+    if reply.windows(KITTY_OK.len()).any(|w| w == KITTY_OK) {
+        Some(Support::Kitty)
+    } else if attrs.split(|&b| b == b';').any(|a| a == b"4") {
+        Some(Support::Sixel)
+    } else {
+        Some(Support::Cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies() {
+        assert_eq!(parse_reply(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;22c"), Some(Support::Kitty));
+        assert_eq!(parse_reply(b"\x1b[?63;1;2;4;6;9;15;22c"), Some(Support::Sixel));
+        assert_eq!(parse_reply(b"\x1b[?64;1;2;6;9;15;18;21;22c"), Some(Support::Cells));
+        // a terminal without kitty graphics may still answer with an error
+        assert_eq!(
+            parse_reply(b"\x1b_Gi=31;ENOTSUPPORTED:no\x1b\\\x1b[?62;4c"),
+            Some(Support::Sixel)
+        );
+        // attribute 42 isn't 4
+        assert_eq!(parse_reply(b"\x1b[?62;42c"), Some(Support::Cells));
+    }
+
+    #[test]
+    fn waits_for_the_whole_reply() {
+        assert_eq!(parse_reply(b""), None);
+        assert_eq!(parse_reply(b"\x1b_Gi=31;OK\x1b\\"), None);
+        assert_eq!(parse_reply(b"\x1b[?62;4"), None);
+    }
+}
@@ -0,0 +1,272 @@
+use std::cmp::Reverse;
+
+use crate::interpreter::framebuffer::Framebuffer;
+
+// how the screen's pixels are laid out on the terminal's character cells
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum RenderMode {
+    // a full block per pixel, which looks stretched vertically
+    #[default]
+    Blocks,
+    // two pixels per cell, one above the other, with ▀ and ▄
+    HalfBlocks,
+    // 2x2 pixels per cell, with the quadrant blocks ▘ ▝ ▖ ▗ and friends
+    Quadrants,
+    // 2x4 pixels per cell, as Braille dots
+    Braille,
+    // each pixel is N rows of 2N full blocks, for large terminals
+    Scaled(u8),
+}
+
+// a character cell; the colours are pixel values, a bit per plane and
+// the steps they have faded above them, with 0 meaning unlit
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: u8,
+    pub bg: u8,
+}
+
+pub const BLANK: Cell = Cell { ch: ' ', fg: 0, bg: 0 };
+
+// indexed by the lit pixels: 1 top left, 2 top right, 4 bottom left, 8 bottom right
+static QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛',
+    '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+// the Braille dot for each pixel of a 2x4 cell, by row
+static BRAILLE_DOTS: [[u8; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+impl RenderMode {
+    pub fn parse(name: &str) -> Option<RenderMode> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "blocks" => Some(RenderMode::Blocks),
+            "half" | "half-blocks" => Some(RenderMode::HalfBlocks),
+            "quadrants" => Some(RenderMode::Quadrants),
+            "braille" => Some(RenderMode::Braille),
+            _ => {
+                // scaled:N
+                let n = name.strip_prefix("scaled:")?.parse().ok()?;
+                if n == 0 {
+                    return None
+                }
+                Some(RenderMode::Scaled(n))
+            },
+        }
+    }
+
+    // pixels per cell when a cell holds several, or cells per
+    // pixel when scaling up, as (width, height)
+    fn pixels_per_cell(&self) -> (usize, usize) {
+        match *self {
+            RenderMode::Blocks | RenderMode::Scaled(_) => (1, 1),
+            RenderMode::HalfBlocks => (1, 2),
+            RenderMode::Quadrants => (2, 2),
+            RenderMode::Braille => (2, 4),
+        }
+    }
+
+    fn scale(&self) -> usize {
+        match *self {
+            RenderMode::Scaled(n) => (n as usize).max(1),
+            _ => 1,
+        }
+    }
+
+    // the size of `fb` in cells, as (columns, rows)
+    pub fn size(&self, fb: &Framebuffer) -> (usize, usize) {
+        let (pw, ph) = self.pixels_per_cell();
+        let cols = fb.width().div_ceil(pw);
+        let rows = fb.height().div_ceil(ph);
+        match *self {
+            RenderMode::Scaled(_) => (cols * 2 * self.scale(), rows * self.scale()),
+            _ => (cols, rows),
+        }
+    }
+
+    // fills `cells` with the screen, row by row
+    pub fn render(&self, fb: &Framebuffer, cells: &mut Vec<Cell>) {
+        let (cols, rows) = self.size(fb);
+        cells.clear();
+        for cy in 0..rows {
+            for cx in 0..cols {
+                cells.push(self.cell(fb, cx, cy));
+            }
+        }
+    }
+
+    fn cell(&self, fb: &Framebuffer, cx: usize, cy: usize) -> Cell {
+        // pixels past the bottom or right edge count as unlit
+        let px = |x: usize, y: usize| {
+            if x < fb.width() && y < fb.height() { fb.pixel(x, y) } else { 0 }
+        };
+
+        match *self {
+            RenderMode::Blocks => block(px(cx, cy)),
+            RenderMode::Scaled(_) => {
+                let n = self.scale();
+                block(px(cx / (2 * n), cy / n))
+            },
+            RenderMode::HalfBlocks => {
+                let (top, bottom) = (px(cx, 2 * cy), px(cx, 2 * cy + 1));
+                match (top, bottom) {
+                    (0, 0) => BLANK,
+                    (t, b) if t == b => block(t),
+                    (t, 0) => Cell { ch: '▀', fg: t, bg: 0 },
+                    (0, b) => Cell { ch: '▄', fg: b, bg: 0 },
+                    (t, b) => Cell { ch: '▀', fg: t, bg: b },
+                }
+            },
+            RenderMode::Quadrants => {
+                let pixels = [
+                    px(2 * cx, 2 * cy), px(2 * cx + 1, 2 * cy),
+                    px(2 * cx, 2 * cy + 1), px(2 * cx + 1, 2 * cy + 1),
+                ];
+                let mask = pixels.iter()
+                    .enumerate()
+                    .filter(|(_, &p)| p != 0)
+                    .fold(0, |m, (i, _)| m | (1 << i));
+                shape(QUADRANTS[mask], &pixels)
+            },
+            RenderMode::Braille => {
+                let mut pixels = [0; 8];
+                let mut dots = 0;
+                for (j, row) in BRAILLE_DOTS.iter().enumerate() {
+                    for (i, dot) in row.iter().enumerate() {
+                        let p = px(2 * cx + i, 4 * cy + j);
+                        pixels[2 * j + i] = p;
+                        if p != 0 {
+                            dots |= dot;
+                        }
+                    }
+                }
+                let ch = match dots {
+                    0 => ' ',
+                    dots => char::from_u32(0x2800 + dots as u32).unwrap_or(' '),
+                };
+                shape(ch, &pixels)
+            },
+        }
+    }
+}
+
+fn block(px: u8) -> Cell {
+    match px {
+        0 => BLANK,
+        px => Cell { ch: '█', fg: px, bg: 0 },
+    }
+}
+
+// cells that hold several pixels can only show one colour, so they
+// take the most common one, or the lowest on a tie, which is the
+// brightest when some are fading
+fn shape(ch: char, pixels: &[u8]) -> Cell {
+    let count = |p: u8| pixels.iter().filter(|&&q| q == p).count();
+    let fg = pixels.iter()
+        .copied()
+        .filter(|&p| p != 0)
+        .max_by_key(|&p| (count(p), Reverse(p)))
+        .unwrap_or(0);
+    match ch {
+        ' ' => BLANK,
+        ch => Cell { ch, fg, bg: 0 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(sprite: &[u8]) -> Framebuffer {
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, sprite, 1, false);
+        fb
+    }
+
+    fn row(mode: RenderMode, fb: &Framebuffer, n: usize) -> String {
+        let mut cells = Vec::new();
+        mode.render(fb, &mut cells);
+        cells.iter().take(n).map(|c| c.ch).collect()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(RenderMode::parse("Braille"), Some(RenderMode::Braille));
+        assert_eq!(RenderMode::parse("half"), Some(RenderMode::HalfBlocks));
+        assert_eq!(RenderMode::parse("scaled:3"), Some(RenderMode::Scaled(3)));
+        assert_eq!(RenderMode::parse("scaled:0"), None);
+        assert_eq!(RenderMode::parse("sixel"), None);
+    }
+
+    #[test]
+    fn sizes() {
+        let mut fb = Framebuffer::new();
+        assert_eq!(RenderMode::Blocks.size(&fb), (64, 32));
+        assert_eq!(RenderMode::HalfBlocks.size(&fb), (64, 16));
+        assert_eq!(RenderMode::Quadrants.size(&fb), (32, 16));
+        assert_eq!(RenderMode::Braille.size(&fb), (32, 8));
+        assert_eq!(RenderMode::Scaled(2).size(&fb), (256, 64));
+        fb.set_hires(true);
+        assert_eq!(RenderMode::Braille.size(&fb), (64, 16));
+    }
+
+    #[test]
+    fn half_blocks() {
+        // rows 10100000 and 11000000
+        let fb = screen(&[0xa0, 0xc0]);
+        assert_eq!(row(RenderMode::HalfBlocks, &fb, 4), "█▄▀ ");
+    }
+
+    #[test]
+    fn quadrants() {
+        // rows 10010000 and 01110000
+        let fb = screen(&[0x90, 0x70]);
+        assert_eq!(row(RenderMode::Quadrants, &fb, 3), "▚▟ ");
+    }
+
+    #[test]
+    fn braille() {
+        let fb = screen(&[0x80, 0x40, 0x80, 0x40]);
+        assert_eq!(row(RenderMode::Braille, &fb, 2), "\u{2895} ");
+    }
+
+    #[test]
+    fn scaled() {
+        let fb = screen(&[0x80]);
+        let mut cells = Vec::new();
+        RenderMode::Scaled(2).render(&fb, &mut cells);
+        let lit: Vec<usize> = cells.iter()
+            .enumerate()
+            .filter(|(_, c)| c.ch == '█')
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(lit, [0, 1, 2, 3, 256, 257, 258, 259]);
+    }
+
+    #[test]
+    fn planes_keep_their_colours() {
+        let mut fb = Framebuffer::new();
+        // plane 1 on the top row, plane 2 on the bottom one
+        fb.draw(0, 0, 8, &[0x80, 0x00, 0x00, 0x80], 3, false);
+        let mut cells = Vec::new();
+        RenderMode::HalfBlocks.render(&fb, &mut cells);
+        assert_eq!(cells[0], Cell { ch: '▀', fg: 1, bg: 2 });
+    }
+
+    #[test]
+    fn shared_cells_take_the_brightest() {
+        let mut fb = Framebuffer::new();
+        fb.pixels[0] = 1 | 2 << 2;
+        fb.pixels[1] = 1 | 1 << 2;
+        let mut cells = Vec::new();
+        RenderMode::Quadrants.render(&fb, &mut cells);
+        assert_eq!(cells[0], Cell { ch: '▀', fg: 1 | 1 << 2, bg: 0 });
+    }
+}
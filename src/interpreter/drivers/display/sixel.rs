@@ -0,0 +1,301 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::interpreter::framebuffer::Framebuffer;
+use super::palette::{Palette, Rgb};
+use super::Display;
+use crate::interpreter::scheduler::{Clock, StdClock};
+use super::terminal::{Pacer, enter_screen, leave_screen};
+
+// draws the screen as a sixel image in the top left corner, for
+// terminals such as xterm -ti vt340, foot, mlterm and WezTerm
+pub struct SixelDisplay<W: Write = io::Stdout, C: Clock = StdClock> {
+    out: W,
+    buf: String,
+    scale: usize,
+    palette: Palette,
+    pacer: Pacer<C>,
+    // the size of the last frame drawn
+    size: Option<(usize, usize)>,
+    sixels: Vec<u8>,
+}
+
+impl SixelDisplay {
+    pub fn new(scale: u8, palette: Palette) -> Self {
+        Self::with_output(io::stdout(), scale, palette)
+    }
+}
+
+impl<W: Write> SixelDisplay<W> {
+    pub fn with_output(out: W, scale: u8, palette: Palette) -> Self {
+        Self::with_clock(out, scale, palette, StdClock::new())
+    }
+}
+
+impl<W: Write, C: Clock> SixelDisplay<W, C> {
+    // paces redraws by `clock` instead of the host's
+    pub fn with_clock(mut out: W, scale: u8, palette: Palette, clock: C) -> Self {
+        enter_screen(&mut out);
+
+        SixelDisplay {
+            out,
+            buf: String::new(),
+            scale: scale.max(1) as usize,
+            palette,
+            pacer: Pacer::with_clock(clock),
+            size: None,
+            sixels: Vec::new(),
+        }
+    }
+
+    // pixels that turn off fade out over this many redraws
+    pub fn set_fade(&mut self, steps: u8) {
+        self.pacer.fade = steps;
+    }
+
+    fn redraw(&mut self) {
+        self.buf.clear();
+        encode(&self.pacer.frame, self.scale, &self.palette, self.pacer.fade, &mut self.sixels, &mut self.buf);
+
+        // a smaller image would leave part of the last one behind
+        let size = (self.pacer.frame.width(), self.pacer.frame.height());
+        if self.size.replace(size) != Some(size) {
+            let _ = write!(self.out, "{}", termion::clear::All);
+        }
+        let _ = write!(self.out, "{}", termion::cursor::Goto(1, 1));
+        let _ = self.out.write_all(self.buf.as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+// percentages, as sixel colour registers take them
+fn percent(c: u8) -> u32 {
+    (c as u32 * 100 + 127) / 255
+}
+
+// terminals such as xterm's vt340 only have this many colour registers
+pub const REGISTERS: usize = 16;
+
+// writes the framebuffer as a sixel image, scaling each pixel to
+// scale x scale. The pixel values on the screen, fading ones
+// included, get colour registers in order, after the four plain
+// colours; once all REGISTERS are taken, a value shares the register
+// with the closest colour
+pub fn encode(fb: &Framebuffer, scale: usize, palette: &Palette, fade: u8, sixels: &mut Vec<u8>, out: &mut String) {
+    let (w, h) = (fb.width() * scale, fb.height() * scale);
+    let _ = write!(out, "\x1bPq\"1;1;{};{}", w, h);
+
+    // the four plain colours are always defined
+    let mut used = [false; 256];
+    used[..4].fill(true);
+    for y in 0..fb.height() {
+        for x in 0..fb.width() {
+            used[fb.pixel(x, y) as usize] = true;
+        }
+    }
+    let mut register = [0_u8; 256];
+    let mut colors: Vec<Rgb> = Vec::with_capacity(REGISTERS);
+    for px in (0..=255).filter(|&px| used[px as usize]) {
+        let c = palette.shade(px, fade);
+        let r = match colors.iter().position(|&d| d == c) {
+            Some(r) => r,
+            None if colors.len() < REGISTERS => {
+                let _ = write!(out, "#{};2;{};{};{}", colors.len(), percent(c.0), percent(c.1), percent(c.2));
+                colors.push(c);
+                colors.len() - 1
+            },
+            None => (0..colors.len()).min_by_key(|&r| distance(colors[r], c)).unwrap_or(0),
+        };
+        register[px as usize] = r as u8;
+    }
+
+    let bands = h.div_ceil(6);
+    for band in 0..bands {
+        // each colour is drawn over the band in turn, going back
+        // to its start with $ between them
+        let mut first = true;
+        for color in 0..colors.len() as u8 {
+            sixels.clear();
+            sixels.extend((0..w).map(|x| {
+                (0..6)
+                    .map(|k| band * 6 + k)
+                    .filter(|&y| y < h && register[fb.pixel(x / scale, y / scale) as usize] == color)
+                    .fold(0, |bits, y| bits | 1 << (y % 6))
+            }));
+            while sixels.last() == Some(&0) {
+                sixels.pop();
+            }
+            if sixels.is_empty() {
+                continue
+            }
+
+            if !first {
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", color);
+            run_length(sixels, out);
+        }
+        if band + 1 < bands {
+            out.push('-');
+        }
+    }
+
+    out.push_str("\x1b\\");
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+// runs of more than three sixels are written as !count
+fn run_length(sixels: &[u8], out: &mut String) {
+    for run in sixels.chunk_by(|a, b| a == b) {
+        let ch = (63 + run[0]) as char;
+        if run.len() > 3 {
+            let _ = write!(out, "!{}{}", run.len(), ch);
+        } else {
+            out.extend(run.iter().map(|_| ch));
+        }
+    }
+}
+
+impl<W: Write, C: Clock> Display for SixelDisplay<W, C> {
+    fn present(&mut self, fb: &Framebuffer) {
+        if self.pacer.present(fb) {
+            self.redraw();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pacer.flush() {
+            self.redraw();
+        }
+    }
+}
+
+impl<W: Write, C: Clock> Drop for SixelDisplay<W, C> {
+    fn drop(&mut self) {
+        leave_screen(&mut self.out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::terminal::tests::{Shared, FakeClock};
+    use super::super::terminal::MIN_REDRAW_INTERVAL;
+
+    #[test]
+    fn bands_and_runs() {
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+
+        let mut out = String::new();
+        encode(&fb, 1, &Palette::default(), 0, &mut Vec::new(), &mut out);
+        assert_eq!(
+            out,
+            concat!(
+                "\x1bPq\"1;1;64;32",
+                "#0;2;0;0;0#1;2;33;100;33#2;2;100;33;33#3;2;100;100;33",
+                // the lit pixel is the top sixel of the first column
+                "#0}!63~$#1@-",
+                "#0!64~-",
+                "#0!64~-",
+                "#0!64~-",
+                "#0!64~-",
+                // the last band only has two rows
+                "#0!64B",
+                "\x1b\\",
+            )
+        );
+    }
+
+    #[test]
+    fn scaling() {
+        let mut fb = Framebuffer::new();
+        fb.draw(1, 0, 8, &[0x80], 2, false);
+
+        let mut out = String::new();
+        encode(&fb, 3, &Palette::default(), 0, &mut Vec::new(), &mut out);
+        assert!(out.starts_with("\x1bPq\"1;1;192;96"));
+        // the second pixel is columns 3 to 5, rows 0 to 2
+        assert!(out.contains("#2???FFF-"));
+    }
+
+    #[test]
+    fn fading_colours() {
+        let mut fb = Framebuffer::new();
+        fb.pixels[0] = 1 | 1 << 2;
+
+        let mut out = String::new();
+        let palette = Palette::with_colors(Rgb(0xff, 0xff, 0xff), Rgb(0, 0, 0));
+        encode(&fb, 1, &palette, 1, &mut Vec::new(), &mut out);
+        // halfway to black, in the register after the plain colours
+        assert!(out.contains("#1;2;100;100;100#2;"));
+        assert!(out.contains("#4;2;50;50;50#0}!63~$#4@-"));
+    }
+
+    #[test]
+    fn at_most_sixteen_registers() {
+        // a pixel on each of 40 steps of fading out
+        let mut fb = Framebuffer::new();
+        for x in 0..40 {
+            fb.pixels[x] = 1 | (x as u8 + 1) << 2;
+        }
+
+        let mut out = String::new();
+        let palette = Palette::with_colors(Rgb(0xff, 0xff, 0xff), Rgb(0, 0, 0));
+        encode(&fb, 1, &palette, 40, &mut Vec::new(), &mut out);
+        let defined: Vec<&str> = out.split('#').filter(|r| r.contains(";2;")).collect();
+        assert_eq!(defined.len(), REGISTERS);
+        assert!(defined.iter().all(|r| r.split(';').next().unwrap().parse::<usize>().unwrap() < REGISTERS));
+        assert!(!out.contains("#16"));
+    }
+
+    #[test]
+    fn draws_in_the_corner() {
+        let out = Shared::default();
+        let mut disp = SixelDisplay::with_output(out.clone(), 2, Palette::default());
+        out.take();
+
+        disp.present(&Framebuffer::new());
+        let first = out.take();
+        assert!(first.starts_with("\x1b[2J\x1b[1;1H\x1bPq\"1;1;128;64"));
+        assert!(first.ends_with("\x1b\\"));
+
+        // the same size doesn't need clearing
+        disp.present(&Framebuffer::new());
+        disp.flush();
+        assert!(out.take().starts_with("\x1b[1;1H\x1bPq"));
+    }
+
+    #[test]
+    fn redraws_at_most_once_a_frame() {
+        let out = Shared::default();
+        let clock = FakeClock::default();
+        let mut disp = SixelDisplay::with_clock(out.clone(), 2, Palette::default(), clock.clone());
+        out.take();
+
+        let mut fb = Framebuffer::new();
+        disp.present(&fb);
+        assert!(out.take().contains("\x1bPq"));
+
+        // too soon for another one, until flushed
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+        clock.advance(MIN_REDRAW_INTERVAL / 2);
+        disp.present(&fb);
+        assert_eq!(out.take(), "");
+        disp.flush();
+        assert!(out.take().contains("\x1bPq"));
+
+        // a frame later it is drawn right away
+        fb.draw(8, 0, 8, &[0x80], 1, false);
+        clock.advance(MIN_REDRAW_INTERVAL);
+        disp.present(&fb);
+        assert!(out.take().contains("\x1bPq"));
+        disp.flush();
+        assert_eq!(out.take(), "");
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::interpreter::framebuffer::Framebuffer;
+use crate::interpreter::scheduler::{Clock, StdClock};
+use super::Display;
+use super::render::{Cell, RenderMode, BLANK};
+use super::palette::Palette;
+use super::phosphor;
+use super::probe::{self, Support};
+
+// the terminal is redrawn at most this often
+pub const MIN_REDRAW_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// how long to wait for the terminal to say what it supports
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// asks the terminal which graphics it can draw, taking
+// character cells if it doesn't say in time
+pub fn detect() -> Support {
+    probe::probe(PROBE_TIMEOUT).unwrap_or(Support::Cells)
+}
+
+// keeps the last frame presented, holding it back if it comes
+// less than MIN_REDRAW_INTERVAL after the last one drawn, and
+// fades out the pixels that turned off if asked to; the time
+// comes from a Clock, the host's unless one is given
+pub struct Pacer<C: Clock = StdClock> {
+    latest: Framebuffer,
+    // what to draw: the latest frame, with the fading pixels
+    pub frame: Framebuffer,
+    pub fade: u8,
+    pending: bool,
+    clock: C,
+    last_redraw: Option<Duration>,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self::with_clock(StdClock::new())
+    }
+}
+
+impl<C: Clock> Pacer<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Pacer {
+            latest: Framebuffer::new(),
+            frame: Framebuffer::new(),
+            fade: 0,
+            pending: false,
+            clock,
+            last_redraw: None,
+        }
+    }
+
+    fn due(&mut self) -> bool {
+        match self.last_redraw {
+            Some(t) => self.clock.now().saturating_sub(t) >= MIN_REDRAW_INTERVAL,
+            None => true,
+        }
+    }
+
+    // whether the frame should be drawn now
+    pub fn present(&mut self, fb: &Framebuffer) -> bool {
+        self.latest.clone_from(fb);
+        self.pending = true;
+        self.due() && self.flush()
+    }
+
+    // whether a frame was held back, or pixels are still fading,
+    // and it should be drawn now
+    pub fn flush(&mut self) -> bool {
+        let fading = self.frame != self.latest;
+        if !(self.pending || fading && self.due()) {
+            return false
+        }
+        self.pending = false;
+        self.last_redraw = Some(self.clock.now());
+        phosphor::decay(&mut self.frame, &self.latest, self.fade);
+        true
+    }
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// switches to the alternate screen and hides the cursor
+pub fn enter_screen<W: Write>(out: &mut W) {
+    let _ = write!(
+        out,
+        "{}{}{}",
+        termion::screen::ToAlternateScreen,
+        termion::cursor::Hide,
+        termion::clear::All,
+    );
+    let _ = out.flush();
+}
+
+pub fn leave_screen<W: Write>(out: &mut W) {
+    let _ = write!(out, "{}{}", termion::screen::ToMainScreen, termion::cursor::Show);
+    let _ = out.flush();
+}
+
+// draws on the terminal's alternate screen, only rewriting the
+// cells that changed since the last frame, in a single write
+pub struct TerminalDisplay<W: Write = io::Stdout, C: Clock = StdClock> {
+    out: W,
+    buf: String,
+    mode: RenderMode,
+    // 24-bit colours, or the terminal's own if None
+    palette: Option<Palette>,
+    pacer: Pacer<C>,
+    cells: Vec<Cell>,
+    // the cells on the terminal and its width in cells, if known
+    shown: Option<(Vec<Cell>, usize)>,
+}
+
+// colours for each combination of the two planes; fading
+// pixels keep theirs, as there are no shades in between
+fn fg_color(px: u8) -> &'static str {
+    use termion::color::*;
+    match px & 3 {
+        1 => LightGreen.fg_str(),
+        2 => LightRed.fg_str(),
+        3 => LightYellow.fg_str(),
+        _ => Reset.fg_str(),
+    }
+}
+
+fn bg_color(px: u8) -> &'static str {
+    use termion::color::*;
+    match px & 3 {
+        1 => LightGreen.bg_str(),
+        2 => LightRed.bg_str(),
+        3 => LightYellow.bg_str(),
+        _ => Reset.bg_str(),
+    }
+}
+
+fn push_color(buf: &mut String, palette: Option<&Palette>, fade: u8, px: u8, fg: bool) {
+    use termion::color::{Fg, Bg, Rgb};
+    match palette.map(|p| p.shade(px, fade)) {
+        Some(c) if fg => { let _ = write!(buf, "{}", Fg(Rgb(c.0, c.1, c.2))); },
+        Some(c) => { let _ = write!(buf, "{}", Bg(Rgb(c.0, c.1, c.2))); },
+        None if fg => buf.push_str(fg_color(px)),
+        None => buf.push_str(bg_color(px)),
+    }
+}
+
+impl TerminalDisplay {
+    pub fn new() -> Self {
+        Self::with_output(io::stdout(), RenderMode::default())
+    }
+
+    pub fn with_mode(mode: RenderMode) -> Self {
+        Self::with_output(io::stdout(), mode)
+    }
+}
+
+impl<W: Write> TerminalDisplay<W> {
+    pub fn with_output(out: W, mode: RenderMode) -> Self {
+        Self::with_clock(out, mode, StdClock::new())
+    }
+}
+
+impl<W: Write, C: Clock> TerminalDisplay<W, C> {
+    // paces redraws by `clock` instead of the host's
+    pub fn with_clock(mut out: W, mode: RenderMode, clock: C) -> Self {
+        enter_screen(&mut out);
+
+        TerminalDisplay {
+            out,
+            buf: String::new(),
+            mode,
+            palette: None,
+            pacer: Pacer::with_clock(clock),
+            cells: Vec::new(),
+            shown: None,
+        }
+    }
+
+    // draws in 24-bit colour, unlit pixels included
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = Some(palette);
+        self.shown = None;
+    }
+
+    // pixels that turn off fade out over this many redraws
+    pub fn set_fade(&mut self, steps: u8) {
+        self.pacer.fade = steps;
+    }
+
+    fn redraw(&mut self) {
+        self.buf.clear();
+
+        self.mode.render(&self.pacer.frame, &mut self.cells);
+        let (cols, _) = self.mode.size(&self.pacer.frame);
+
+        let shown = match self.shown.take() {
+            Some((shown, w)) if w == cols && shown.len() == self.cells.len() => shown,
+            // everything has to be drawn again; with a palette, blank
+            // cells have a colour of their own and can't be left out
+            _ => {
+                let _ = write!(self.buf, "{}", termion::clear::All);
+                let blank = match self.palette {
+                    Some(_) => Cell { ch: '\0', ..BLANK },
+                    None => BLANK,
+                };
+                vec![blank; self.cells.len()]
+            },
+        };
+        // what colours are set after a reset
+        let reset = match self.palette {
+            Some(_) => (None, None),
+            None => (Some(0), Some(0)),
+        };
+
+        for (y, (row, old)) in self.cells.chunks(cols).zip(shown.chunks(cols)).enumerate() {
+            // the cursor is only moved to the start of each run of
+            // changed cells, and colours only set when they change
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] == old[x] {
+                    x += 1;
+                    continue
+                }
+                let _ = write!(self.buf, "{}", termion::cursor::Goto(x as u16 + 1, y as u16 + 1));
+                let (mut fg, mut bg) = reset;
+                let (palette, fade) = (self.palette.as_ref(), self.pacer.fade);
+                while x < row.len() && row[x] != old[x] {
+                    let cell = row[x];
+                    if fg != Some(cell.fg) && cell.ch != ' ' {
+                        push_color(&mut self.buf, palette, fade, cell.fg, true);
+                        fg = Some(cell.fg);
+                    }
+                    if bg != Some(cell.bg) {
+                        push_color(&mut self.buf, palette, fade, cell.bg, false);
+                        bg = Some(cell.bg);
+                    }
+                    self.buf.push(cell.ch);
+                    x += 1;
+                }
+                let _ = write!(self.buf, "{}", termion::style::Reset);
+            }
+        }
+
+        if !self.buf.is_empty() {
+            let _ = self.out.write_all(self.buf.as_bytes());
+            let _ = self.out.flush();
+        }
+        let mut shown = shown;
+        shown.clone_from(&self.cells);
+        self.shown = Some((shown, cols));
+    }
+}
+
+impl Default for TerminalDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write, C: Clock> Display for TerminalDisplay<W, C> {
+    fn present(&mut self, fb: &Framebuffer) {
+        if self.pacer.present(fb) {
+            self.redraw();
+        }
+    }
+
+    // shows the last frame presented, if it was held back because
+    // it came too soon after the previous one, or any pixels fading
+    fn flush(&mut self) {
+        if self.pacer.flush() {
+            self.redraw();
+        }
+    }
+}
+
+impl<W: Write, C: Clock> Drop for TerminalDisplay<W, C> {
+    fn drop(&mut self) {
+        leave_screen(&mut self.out);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // lets the test look at what was written while the display owns it
+    #[derive(Clone, Default)]
+    pub struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        pub fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.borrow_mut())).unwrap()
+        }
+    }
+
+    // a clock that only moves when the test moves it
+    #[derive(Clone, Default)]
+    pub struct FakeClock(std::rc::Rc<std::cell::Cell<Duration>>);
+
+    impl FakeClock {
+        pub fn advance(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&mut self) -> Duration {
+            self.0.get()
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.advance(duration);
+        }
+    }
+
+    #[test]
+    fn only_changes_are_redrawn() {
+        let out = Shared::default();
+        let clock = FakeClock::default();
+        let mut disp = TerminalDisplay::with_clock(out.clone(), RenderMode::Blocks, clock.clone());
+        assert!(out.take().starts_with("\x1b[?1049h"));
+
+        let mut fb = Framebuffer::new();
+        fb.draw(10, 5, 8, &[0x80], 1, false);
+        disp.present(&fb);
+        let first = out.take();
+        assert!(first.contains("\x1b[6;11H"));
+        assert!(first.contains('█'));
+
+        // too soon for another redraw, until flushed
+        fb.draw(20, 7, 8, &[0x80], 1, false);
+        clock.advance(MIN_REDRAW_INTERVAL / 2);
+        disp.present(&fb);
+        assert_eq!(out.take(), "");
+        disp.flush();
+        let second = out.take();
+        assert_eq!(second.matches('█').count(), 1);
+        assert!(second.contains("\x1b[8;21H"));
+        assert!(!second.contains("\x1b[6;11H"));
+
+        // nothing changed, nothing written
+        disp.present(&fb);
+        disp.flush();
+        assert_eq!(out.take(), "");
+
+        // in time for the next redraw, which is drawn right away
+        clock.advance(MIN_REDRAW_INTERVAL);
+        fb.draw(20, 7, 8, &[0x80], 1, false);
+        disp.present(&fb);
+        assert!(out.take().contains("\x1b[8;21H"));
+
+        drop(disp);
+        assert!(out.take().contains("\x1b[?1049l"));
+    }
+
+    #[test]
+    fn palette_and_fading() {
+        let out = Shared::default();
+        let clock = FakeClock::default();
+        let mut disp = TerminalDisplay::with_clock(out.clone(), RenderMode::Blocks, clock.clone());
+        disp.set_palette(Palette::theme("amber").unwrap());
+        disp.set_fade(1);
+        out.take();
+
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+        disp.present(&fb);
+        let first = out.take();
+        // blank cells are drawn in the background colour too
+        assert_eq!(first.matches("\x1b[48;2;20;12;0m").count(), 32);
+        assert!(first.contains("\x1b[38;2;255;176;0m\x1b[48;2;20;12;0m█"));
+
+        // the pixel is erased, and drawn halfway to the background
+        fb.draw(0, 0, 8, &[0x80], 1, false);
+        disp.present(&fb);
+        disp.flush();
+        assert_eq!(out.take(), "\x1b[1;1H\x1b[38;2;138;94;0m\x1b[48;2;20;12;0m█\x1b[m");
+
+        // then gone, at the next redraw
+        disp.flush();
+        assert_eq!(out.take(), "");
+        clock.advance(MIN_REDRAW_INTERVAL);
+        disp.flush();
+        assert_eq!(out.take(), "\x1b[1;1H\x1b[48;2;20;12;0m \x1b[m");
+        disp.flush();
+        assert_eq!(out.take(), "");
+    }
+}
@@ -0,0 +1,167 @@
+// a small zlib compressor (RFC 1950 and 1951) for the kitty display's
+// frames: a single deflate block with the fixed Huffman codes, and
+// greedy matching against the last place each 3 bytes were seen. That
+// is far from the best deflate can do, but screens made of a few flat
+// colours shrink to a fraction of their size all the same
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+// the first length of each length code from 257, and its extra bits
+const LENGTHS: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+// the same for distance codes
+const DISTANCES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10),
+    (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+// deflate packs bits starting from the least significant one
+struct Bits {
+    out: Vec<u8>,
+    acc: u32,
+    n: u32,
+}
+
+impl Bits {
+    fn put(&mut self, bits: u32, n: u32) {
+        self.acc |= bits << self.n;
+        self.n += n;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn symbol(&mut self, sym: u16) {
+        match sym {
+            0..=143 => self.code(0x30 + sym as u32, 8),
+            144..=255 => self.code(0x190 + (sym - 144) as u32, 9),
+            256..=279 => self.code((sym - 256) as u32, 7),
+            _ => self.code(0xc0 + (sym - 280) as u32, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+// the code for `value` in `table`, and what's left for the extra bits
+fn lookup(table: &[(u16, u8)], value: usize) -> (usize, u32, u8) {
+    let code = table.iter().rposition(|&(base, _)| base as usize <= value).unwrap_or(0);
+    let (base, extra) = table[code];
+    (code, (value - base as usize) as u32, extra)
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut bits = Bits { out: vec![0x78, 0x01], acc: 0, n: 0 };
+    // BFINAL, then BTYPE 01 for the fixed codes
+    bits.put(1, 1);
+    bits.put(1, 2);
+
+    // where each hash was last seen, plus one
+    let mut head = vec![0_usize; 1 << HASH_BITS];
+    let mut i = 0;
+    while i < data.len() {
+        let mut len = 0;
+        let mut dist = 0;
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(&data[i..]);
+            if let Some(j) = head[h].checked_sub(1).filter(|&j| i - j <= WINDOW) {
+                let max = (data.len() - i).min(MAX_MATCH);
+                len = (0..max).take_while(|&k| data[j + k] == data[i + k]).count();
+                dist = i - j;
+            }
+            head[h] = i + 1;
+        }
+
+        if len < MIN_MATCH {
+            bits.symbol(data[i] as u16);
+            i += 1;
+            continue
+        }
+        let (code, rest, extra) = lookup(&LENGTHS, len);
+        bits.symbol(257 + code as u16);
+        bits.put(rest, extra as u32);
+        let (code, rest, extra) = lookup(&DISTANCES, dist);
+        bits.code(code as u32, 5);
+        bits.put(rest, extra as u32);
+
+        // the bytes matched are looked up by later matches too
+        for k in i + 1..(i + len).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            head[hash(&data[k..])] = k + 1;
+        }
+        i += len;
+    }
+    bits.symbol(256);
+
+    let mut out = bits.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for chunk in data.chunks(5552) {
+        for &x in chunk {
+            a += x as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn literals_and_matches() {
+        // checked against zlib's inflate
+        assert_eq!(compress(b""), [0x78, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(compress(b"a"), [0x78, 0x01, 0x4b, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]);
+        // abc, then 9 bytes from 3 back
+        assert_eq!(
+            compress(b"abcabcabcabc"),
+            [0x78, 0x01, 0x4b, 0x4c, 0x4a, 0x86, 0x23, 0x00, 0x1d, 0xe0, 0x04, 0x99],
+        );
+    }
+
+    #[test]
+    fn long_runs() {
+        let data = vec![0x55; 64 * 32 * 3];
+        assert!(compress(&data).len() < 100);
+    }
+}
@@ -1,6 +1,11 @@
-use std::ops;
-use std::mem;
-use std::convert::TryFrom;
+use core::ops;
+use core::convert::TryFrom;
+
+#[cfg(feature = "std")]
+pub mod terminal;
+
+#[cfg(feature = "std")]
+pub use terminal::{TerminalInput, HeldKeys, Hotkey, DEFAULT_HOLD_TIME};
 
 #[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -23,15 +28,20 @@ pub enum Key {
     F,
 }
 
+static KEYS: [Key; 16] = [
+    Key::Zero, Key::One, Key::Two, Key::Three,
+    Key::Four, Key::Five, Key::Six, Key::Seven,
+    Key::Eight, Key::Nine, Key::A, Key::B,
+    Key::C, Key::D, Key::E, Key::F,
+];
+
 impl TryFrom<u8> for Key {
     type Error = &'static str;
 
     fn try_from(k: u8) -> Result<Key, Self::Error> {
-        if k < 16 {
-            unsafe { Ok(mem::transmute(k)) }
-        } else {
-            Err("key out of range")
-        }
+        KEYS.get(k as usize)
+            .copied()
+            .ok_or("key out of range")
     }
 }
 
@@ -46,17 +56,115 @@ impl ops::Index<Key> for KeySet {
     }
 }
 
+impl KeySet {
+    // the lowest key held down, if any
+    pub fn first(&self) -> Option<Key> {
+        KEYS.iter().copied().find(|&k| self[k])
+    }
+}
+
+impl From<[bool; 16]> for KeySet {
+    fn from(keys: [bool; 16]) -> KeySet {
+        KeySet(keys)
+    }
+}
+
+// the keypad as it is right now; the VM polls it whenever a program
+// looks at the keys, FX0A included, so nothing here has to block
 pub trait Input {
     fn poll_keyboard(&mut self) -> KeySet;
-    fn wait_key(&mut self) -> Key;
 }
 
 impl Input for () {
     fn poll_keyboard(&mut self) -> KeySet {
         KeySet([false; 16])
     }
+}
+
+// the keys that stand for the COSMAC VIP keypad, read left to
+// right and top to bottom:
+//
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Keymap([char; 16]);
+
+// the keypad in the order the keymap lists it
+static KEYPAD: [Key; 16] = [
+    Key::One, Key::Two, Key::Three, Key::C,
+    Key::Four, Key::Five, Key::Six, Key::D,
+    Key::Seven, Key::Eight, Key::Nine, Key::E,
+    Key::A, Key::Zero, Key::B, Key::F,
+];
+
+pub const LAYOUTS: [(&str, &str); 4] = [
+    ("qwerty", "1234qwerasdfzxcv"),
+    ("azerty", "1234azerqsdfwxcv"),
+    ("qwertz", "1234qwerasdfyxcv"),
+    ("dvorak", "1234',.paoeu;qjk"),
+];
+
+impl Keymap {
+    // one of LAYOUTS, or the 16 keys to use in keypad order; the
+    // same key can't stand for two keypad keys
+    pub fn parse(s: &str) -> Option<Keymap> {
+        let s = LAYOUTS.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map_or(s, |(_, keys)| keys);
+
+        let mut keys = ['\0'; 16];
+        let mut chars = s.chars().map(|c| c.to_ascii_lowercase());
+        for i in 0..16 {
+            let c = chars.next()?;
+            if keys[..i].contains(&c) {
+                return None
+            }
+            keys[i] = c;
+        }
+        match chars.next() {
+            Some(_) => None,
+            None => Some(Keymap(keys)),
+        }
+    }
+
+    pub fn map(&self, c: char) -> Option<Key> {
+        let c = c.to_ascii_lowercase();
+        self.0.iter()
+            .position(|&k| k == c)
+            .map(|i| KEYPAD[i])
+    }
+}
+
+// the keypad's shape on the left of a QWERTY keyboard
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::parse(LAYOUTS[0].1).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymaps() {
+        let qwerty = Keymap::default();
+        assert_eq!(qwerty.map('x'), Some(Key::Zero));
+        assert_eq!(qwerty.map('V'), Some(Key::F));
+        assert_eq!(qwerty.map('4'), Some(Key::C));
+        assert_eq!(qwerty.map('p'), None);
+
+        assert_eq!(Keymap::parse("AZERTY").unwrap().map('w'), Some(Key::A));
+        assert_eq!(Keymap::parse("x123qweasdzc4rfv").unwrap().map('x'), Some(Key::One));
+        for (_, keys) in LAYOUTS.iter() {
+            assert!(Keymap::parse(keys).is_some(), "{}", keys);
+        }
 
-    fn wait_key(&mut self) -> Key {
-        loop {}
+        // too short, too long, or a key used twice
+        assert_eq!(Keymap::parse("1234"), None);
+        assert_eq!(Keymap::parse("1234qwerasdfzxcvb"), None);
+        assert_eq!(Keymap::parse("1234qwerasdfzxcc"), None);
     }
 }
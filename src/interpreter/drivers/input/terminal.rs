@@ -0,0 +1,181 @@
+use std::thread;
+use std::process;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use termion::event;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+
+use crate::interpreter::scheduler::{Clock, StdClock};
+use super::{Input, Key, KeySet, Keymap};
+
+// terminals only report key presses, so a key is considered
+// held for this long after the last press (or auto-repeat)
+pub const DEFAULT_HOLD_TIME: Duration = Duration::from_millis(150);
+
+// front-end commands bound to keys outside the keypad
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+}
+
+// which keys count as held: each is from when it was last pressed,
+// by `clock`, until `hold` has gone by
+pub struct HeldKeys<C: Clock = StdClock> {
+    pressed: [Option<Duration>; 16],
+    hold: Duration,
+    clock: C,
+}
+
+impl<C: Clock> HeldKeys<C> {
+    pub fn new(hold: Duration, clock: C) -> Self {
+        HeldKeys { pressed: [None; 16], hold, clock }
+    }
+
+    pub fn press(&mut self, k: Key) {
+        self.pressed[k as usize] = Some(self.clock.now());
+    }
+
+    pub fn keys(&mut self) -> KeySet {
+        let now = self.clock.now();
+        let mut keys = [false; 16];
+
+        for (held, pressed) in keys.iter_mut().zip(self.pressed.iter()) {
+            *held = match pressed {
+                Some(t) => now.saturating_sub(*t) < self.hold,
+                None => false,
+            };
+        }
+
+        KeySet(keys)
+    }
+}
+
+pub struct TerminalInput<C: Clock = StdClock> {
+    events: Receiver<Key>,
+    hotkeys: Receiver<Hotkey>,
+    held: HeldKeys<C>,
+    raw: Arc<Mutex<RawTerminal<File>>>,
+}
+
+impl TerminalInput {
+    pub fn new() -> io::Result<Self> {
+        Self::with_keymap(Keymap::default(), DEFAULT_HOLD_TIME)
+    }
+
+    pub fn with_keymap(keymap: Keymap, hold: Duration) -> io::Result<Self> {
+        Self::with_clock(keymap, hold, StdClock::new())
+    }
+}
+
+impl<C: Clock> TerminalInput<C> {
+    // times how long keys are held by `clock` instead of the host's
+    pub fn with_clock(keymap: Keymap, hold: Duration, clock: C) -> io::Result<Self> {
+        let tty = termion::get_tty()?;
+        let raw = Arc::new(Mutex::new(tty.try_clone()?.into_raw_mode()?));
+        let (tx, events) = mpsc::channel();
+        let (hotkey_tx, hotkeys) = mpsc::channel();
+
+        // the interpreter only polls the keyboard when a ROM asks
+        // for it, so quitting has to be handled on this side
+        let term = Arc::clone(&raw);
+        thread::spawn(move || {
+            for k in tty.keys() {
+                match k {
+                    Ok(event::Key::Ctrl('c')) | Err(_) => quit(&term),
+                    Ok(event::Key::Char(c)) => if let Some(k) = keymap.map(c) {
+                        if tx.send(k).is_err() {
+                            break
+                        }
+                    },
+                    Ok(event::Key::F(5)) => {
+                        let _ = hotkey_tx.send(Hotkey::SaveState);
+                    },
+                    Ok(event::Key::F(9)) => {
+                        let _ = hotkey_tx.send(Hotkey::LoadState);
+                    },
+                    Ok(_) => (),
+                }
+            }
+        });
+
+        Ok(TerminalInput { events, hotkeys, held: HeldKeys::new(hold, clock), raw })
+    }
+
+    // F5 saves the state, F9 loads it
+    pub fn take_hotkey(&mut self) -> Option<Hotkey> {
+        self.hotkeys.try_recv().ok()
+    }
+}
+
+impl<C: Clock> Drop for TerminalInput<C> {
+    fn drop(&mut self) {
+        if let Ok(raw) = self.raw.lock() {
+            let _ = raw.suspend_raw_mode();
+        }
+    }
+}
+
+// raw mode disables the terminal's own signal handling,
+// so we have to restore it ourselves before leaving
+fn quit(raw: &Mutex<RawTerminal<File>>) -> ! {
+    if let Ok(mut raw) = raw.lock() {
+        let _ = raw.suspend_raw_mode();
+        let _ = write!(raw, "{}{}", termion::screen::ToMainScreen, termion::cursor::Show);
+        let _ = raw.flush();
+    }
+    process::exit(0)
+}
+
+impl<C: Clock> Input for TerminalInput<C> {
+    fn poll_keyboard(&mut self) -> KeySet {
+        while let Ok(k) = self.events.try_recv() {
+            self.held.press(k);
+        }
+        self.held.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::drivers::display::terminal::tests::FakeClock;
+
+    #[test]
+    fn keys_are_held_for_a_while() {
+        let clock = FakeClock::default();
+        let mut held = HeldKeys::new(DEFAULT_HOLD_TIME, clock.clone());
+        assert_eq!(held.keys(), KeySet([false; 16]));
+
+        held.press(Key::A);
+        clock.advance(DEFAULT_HOLD_TIME / 2);
+        assert!(held.keys()[Key::A]);
+        assert!(!held.keys()[Key::B]);
+
+        // auto-repeat keeps it down
+        held.press(Key::A);
+        clock.advance(DEFAULT_HOLD_TIME / 2);
+        assert!(held.keys()[Key::A]);
+
+        clock.advance(DEFAULT_HOLD_TIME / 2);
+        assert!(!held.keys()[Key::A]);
+    }
+
+    #[test]
+    fn keys_are_let_go_of_one_by_one() {
+        let clock = FakeClock::default();
+        let mut held = HeldKeys::new(Duration::from_millis(100), clock.clone());
+        held.press(Key::One);
+        clock.advance(Duration::from_millis(60));
+        held.press(Key::Two);
+        clock.advance(Duration::from_millis(40));
+        let keys = held.keys();
+        assert!(!keys[Key::One]);
+        assert!(keys[Key::Two]);
+    }
+}
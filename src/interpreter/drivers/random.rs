@@ -0,0 +1,100 @@
+// the source of CXNN's random bytes, which the host supplies; without
+// std there is no clock to seed Xorshift from, so it has to bring a seed
+pub trait Random {
+    fn byte(&mut self) -> u8;
+}
+
+// xorshift64*, small and fast and good enough for games;
+// the same seed always gives the same bytes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub const fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        let state = if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed };
+        Xorshift { state }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_clock() -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Xorshift {
+    fn default() -> Self {
+        Self::from_clock()
+    }
+}
+
+impl Random for Xorshift {
+    fn byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8
+    }
+}
+
+// plays back the given bytes over and over, for tests and
+// for replaying a run whose random bytes were recorded
+#[derive(Clone, Debug)]
+pub struct Replay<T> {
+    bytes: T,
+    next: usize,
+}
+
+impl<T: AsRef<[u8]>> Replay<T> {
+    pub fn new(bytes: T) -> Self {
+        Replay { bytes, next: 0 }
+    }
+}
+
+impl<T: AsRef<[u8]>> Random for Replay<T> {
+    fn byte(&mut self) -> u8 {
+        let bytes = self.bytes.as_ref();
+        if bytes.is_empty() {
+            return 0
+        }
+        let b = bytes[self.next % bytes.len()];
+        self.next = (self.next + 1) % bytes.len();
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xorshift_is_reproducible() {
+        let mut a = Xorshift::new(42);
+        let mut b = Xorshift::new(42);
+        let mut c = Xorshift::new(43);
+        let a: Vec<u8> = (0..64).map(|_| a.byte()).collect();
+        let b: Vec<u8> = (0..64).map(|_| b.byte()).collect();
+        let c: Vec<u8> = (0..64).map(|_| c.byte()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn replay_repeats() {
+        let mut r = Replay::new([1, 2, 3]);
+        let bytes: Vec<u8> = (0..7).map(|_| r.byte()).collect();
+        assert_eq!(bytes, [1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(Replay::new([]).byte(), 0);
+    }
+}
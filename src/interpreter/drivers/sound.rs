@@ -1,9 +1,45 @@
+#[cfg(feature = "std")]
+pub mod wav;
+
 pub trait Sound {
     fn beep_start(&mut self);
     fn beep_end(&mut self);
+
+    // XO-CHIP 1-bit audio pattern, played back MSB first
+    fn set_pattern(&mut self, _pattern: &[u8; 16]) {}
+
+    // XO-CHIP playback rate, 4000*2^((pitch-64)/48) bits per second
+    fn set_pitch(&mut self, _pitch: u8) {}
+
+    // called at the end of each 60 Hz frame, before the sound timer
+    // ticks, for drivers that follow emulated time rather than the clock
+    fn end_frame(&mut self) {}
 }
 
 impl Sound for () {
     fn beep_start(&mut self) {}
     fn beep_end(&mut self) {}
 }
+
+#[cfg(feature = "std")]
+impl<S: Sound + ?Sized> Sound for Box<S> {
+    fn beep_start(&mut self) {
+        (**self).beep_start()
+    }
+
+    fn beep_end(&mut self) {
+        (**self).beep_end()
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16]) {
+        (**self).set_pattern(pattern)
+    }
+
+    fn set_pitch(&mut self, pitch: u8) {
+        (**self).set_pitch(pitch)
+    }
+
+    fn end_frame(&mut self) {
+        (**self).end_frame()
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::Sound;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const DEFAULT_FREQUENCY: u32 = 440;
+pub const DEFAULT_VOLUME: u8 = 25;
+
+// where the sizes that depend on the length of the data go
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+const HEADER_SIZE: u32 = 44;
+
+// records the beeps as a square wave in a 16-bit mono PCM WAV file,
+// a frame's worth of samples at the end of each emulated frame, so
+// the recording follows the program rather than the clock; the header
+// is kept up to date after every frame, so the file can be played
+// even if the program is killed
+pub struct WavSound<W: Write + Seek = File> {
+    out: W,
+    frequency: u32,
+    amplitude: i16,
+    on: bool,
+    frames: u64,
+    samples: u64,
+    buf: Vec<u8>,
+    // the first write that failed, as the driver can't report it
+    error: Option<io::Error>,
+}
+
+impl WavSound {
+    pub fn create<P: AsRef<Path>>(path: P, frequency: u32, volume: u8) -> io::Result<Self> {
+        Self::new(File::create(path)?, frequency, volume)
+    }
+}
+
+impl<W: Write + Seek> WavSound<W> {
+    // a tone of `frequency` Hz, at `volume` percent of full scale
+    pub fn new(mut out: W, frequency: u32, volume: u8) -> io::Result<Self> {
+        out.write_all(&header(0))?;
+        Ok(WavSound {
+            out,
+            frequency: frequency.clamp(1, SAMPLE_RATE / 2),
+            amplitude: (i16::MAX as i32 * volume.min(100) as i32 / 100) as i16,
+            on: false,
+            frames: 0,
+            samples: 0,
+            buf: Vec::new(),
+            error: None,
+        })
+    }
+
+    // the output, or the first error writing it
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.out),
+        }
+    }
+
+    fn sample(&self, n: u64) -> i16 {
+        // high in the first half of each period
+        let half_periods = n * 2 * self.frequency as u64 / SAMPLE_RATE as u64;
+        let high = half_periods % 2 == 0;
+        match (self.on, high) {
+            (false, _) => 0,
+            (true, true) => self.amplitude,
+            (true, false) => -self.amplitude,
+        }
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        // the samples due by the end of this frame
+        self.frames += 1;
+        let end = self.frames * SAMPLE_RATE as u64 / 60;
+        self.buf.clear();
+        for n in self.samples..end {
+            self.buf.extend_from_slice(&self.sample(n).to_le_bytes());
+        }
+        self.samples = end;
+        self.out.write_all(&self.buf)?;
+
+        let data = (self.samples * 2) as u32;
+        self.out.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.out.write_all(&(HEADER_SIZE - 8 + data).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.out.write_all(&data.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+fn header(data: u32) -> [u8; HEADER_SIZE as usize] {
+    let mut h = [0; HEADER_SIZE as usize];
+    let channels: u16 = 1;
+    let bits: u16 = 16;
+    let block = channels * bits / 8;
+
+    h[0..4].copy_from_slice(b"RIFF");
+    h[4..8].copy_from_slice(&(HEADER_SIZE - 8 + data).to_le_bytes());
+    h[8..12].copy_from_slice(b"WAVE");
+    h[12..16].copy_from_slice(b"fmt ");
+    h[16..20].copy_from_slice(&16_u32.to_le_bytes());
+    // PCM
+    h[20..22].copy_from_slice(&1_u16.to_le_bytes());
+    h[22..24].copy_from_slice(&channels.to_le_bytes());
+    h[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    h[28..32].copy_from_slice(&(SAMPLE_RATE * block as u32).to_le_bytes());
+    h[32..34].copy_from_slice(&block.to_le_bytes());
+    h[34..36].copy_from_slice(&bits.to_le_bytes());
+    h[36..40].copy_from_slice(b"data");
+    h[40..44].copy_from_slice(&data.to_le_bytes());
+    h
+}
+
+impl<W: Write + Seek> Sound for WavSound<W> {
+    fn beep_start(&mut self) {
+        self.on = true;
+    }
+
+    fn beep_end(&mut self) {
+        self.on = false;
+    }
+
+    fn end_frame(&mut self) {
+        if self.error.is_none() {
+            self.error = self.write_frame().err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::interpreter::VM;
+    use crate::interpreter::drivers::Context;
+    use crate::interpreter::quirks::Quirks;
+
+    fn samples(wav: &[u8]) -> Vec<i16> {
+        wav[HEADER_SIZE as usize..]
+            .chunks(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn square_wave() {
+        // 441 Hz is 100 samples a period
+        let mut wav = WavSound::new(Cursor::new(Vec::new()), 441, 50).unwrap();
+        wav.beep_start();
+        wav.end_frame();
+        wav.beep_end();
+        wav.end_frame();
+        let wav = wav.into_inner().unwrap().into_inner();
+
+        assert_eq!(wav.len(), 44 + 2 * 1470);
+        assert_eq!(&wav[..HEADER_SIZE as usize], &header(2 * 1470));
+        let s = samples(&wav);
+        assert!(s[..50].iter().all(|&s| s == 16383));
+        assert!(s[50..100].iter().all(|&s| s == -16383));
+        assert_eq!(s[700], 16383);
+        assert!(s[735..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn follows_the_sound_timer() {
+        // LD V0, 3; LD ST, V0; JP 0x204
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0x60, 0x03, 0xf0, 0x18, 0x12, 0x04]).unwrap();
+        let wav = WavSound::new(Cursor::new(Vec::new()), DEFAULT_FREQUENCY, 100).unwrap();
+        let mut ctx = Context::new((), (), wav);
+        for _ in 0..5 {
+            vm.run_frame(&mut ctx).unwrap();
+        }
+
+        // the timer beeps for three frames, whatever the host's clock
+        let Context { sound, .. } = ctx;
+        let wav = sound.into_inner().unwrap().into_inner();
+        let frames: Vec<bool> = samples(&wav)
+            .chunks(735)
+            .map(|f| f.iter().any(|&s| s != 0))
+            .collect();
+        assert_eq!(frames, [true, true, true, false, false]);
+    }
+}
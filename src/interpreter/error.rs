@@ -0,0 +1,121 @@
+use core::fmt;
+use core::error;
+
+// faults caused by the program being run; the instruction at fault
+// is left unexecuted, with `pc` pointing at it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VmError {
+    // RET with nothing on the stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    // CALL with all 16 levels in use
+    StackOverflow { pc: u16, opcode: u16 },
+    // a read or write of `len` bytes at `addr` runs past the end of RAM
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: u16, len: usize },
+    // SKP or SKNP on a register holding something other than 0-F
+    InvalidKey { pc: u16, opcode: u16, key: u8 },
+    // execution ran off the end of RAM
+    PcOutOfBounds { pc: u16 },
+    // the program doesn't fit between its load address and the end of RAM
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            VmError::StackUnderflow { pc, opcode } => {
+                write!(f, "0x{:03X} ({:04X}): return with an empty stack", pc, opcode)
+            },
+            VmError::StackOverflow { pc, opcode } => {
+                write!(f, "0x{:03X} ({:04X}): call stack overflow", pc, opcode)
+            },
+            VmError::MemoryOutOfBounds { pc, opcode, addr, len } => write!(
+                f,
+                "0x{:03X} ({:04X}): access of {} bytes at 0x{:04X} is past the end of memory",
+                pc, opcode, len, addr,
+            ),
+            VmError::InvalidKey { pc, opcode, key } => {
+                write!(f, "0x{:03X} ({:04X}): no such key 0x{:02X}", pc, opcode, key)
+            },
+            VmError::PcOutOfBounds { pc } => {
+                write!(f, "0x{:04X}: program counter is past the end of memory", pc)
+            },
+            VmError::RomTooLarge { size, max } => {
+                write!(f, "program is {} bytes, but at most {} fit in memory", size, max)
+            },
+        }
+    }
+}
+
+impl error::Error for VmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{VM, RAM_SIZE, quirks::Quirks, drivers::Context};
+
+    fn run(rom: &[u8], steps: usize) -> Result<VM, VmError> {
+        let mut vm = VM::new(Quirks::default());
+        vm.load(rom)?;
+        let mut ctx = Context::new((), (), ());
+        for _ in 0..steps {
+            vm.step(&mut ctx)?;
+        }
+        Ok(vm)
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let e = run(&[0x00, 0xee], 1).err();
+        assert_eq!(e, Some(VmError::StackUnderflow { pc: 0x200, opcode: 0x00ee }));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // calls itself forever
+        let e = run(&[0x22, 0x00], 17).err();
+        assert_eq!(e, Some(VmError::StackOverflow { pc: 0x200, opcode: 0x2200 }));
+        assert!(run(&[0x22, 0x00], 16).is_ok());
+    }
+
+    #[test]
+    fn add_wraps_around() {
+        let vm = run(&[0x60, 0xff, 0x70, 0x02], 2).unwrap();
+        assert_eq!(vm.registers()[0], 1);
+    }
+
+    #[test]
+    fn sprite_past_end_of_memory() {
+        // LD I, LONG 0xFFFC; DRW V0, V0, 5
+        let e = run(&[0xf0, 0x00, 0xff, 0xfc, 0xd0, 0x05], 2).err();
+        assert_eq!(e, Some(VmError::MemoryOutOfBounds {
+            pc: 0x204,
+            opcode: 0xd005,
+            addr: 0xfffc,
+            len: 5,
+        }));
+    }
+
+    #[test]
+    fn invalid_key() {
+        let e = run(&[0x60, 0x10, 0xe0, 0x9e], 2).err();
+        assert_eq!(e, Some(VmError::InvalidKey { pc: 0x202, opcode: 0xe09e, key: 0x10 }));
+    }
+
+    #[test]
+    fn rom_too_large() {
+        let max = RAM_SIZE - 0x200;
+        assert!(run(&vec![0; max], 0).is_ok());
+        let e = run(&vec![0; max + 1], 0).err();
+        assert_eq!(e, Some(VmError::RomTooLarge { size: max + 1, max }));
+    }
+
+    #[test]
+    fn running_off_the_end() {
+        // 0000 does nothing, so this just walks through memory
+        let rom = vec![0; RAM_SIZE - 0x200];
+        let steps = (0xfffe - 0x200) / 2;
+        assert!(run(&rom, steps).is_ok());
+        let e = run(&rom, steps + 1).err();
+        assert_eq!(e, Some(VmError::PcOutOfBounds { pc: 0xfffe }));
+    }
+}
@@ -0,0 +1,226 @@
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+// SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const HIRES_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT;
+
+// XO-CHIP bitplanes, each pixel holds one bit per plane
+pub const PLANES: usize = 2;
+
+// the screen, owned by the VM, which draws sprites and scrolls it;
+// displays are only handed the result to show
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Framebuffer {
+    pub hires: bool,
+    // one byte per pixel, row by row, only the first
+    // DISPLAY_WIDTH * DISPLAY_HEIGHT are used in low resolution
+    pub pixels: [u8; HIRES_SIZE],
+}
+
+impl Framebuffer {
+    pub const fn new() -> Self {
+        Framebuffer { hires: false, pixels: [0; HIRES_SIZE] }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    // the planes lit at (x, y), one bit each
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y*self.width() + x]
+    }
+
+    // the rows on screen, `width()` pixels each
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let (w, h) = (self.width(), self.height());
+        self.pixels[..w*h].chunks(w)
+    }
+
+    // the screen as a plain PBM image, one line per row, so it can
+    // be read in a diff; any plane lit counts as black
+    #[cfg(feature = "std")]
+    pub fn to_pbm(&self) -> String {
+        let mut out = format!("P1\n{} {}\n", self.width(), self.height());
+        for row in self.rows() {
+            for &px in row {
+                out.push(if px != 0 { '1' } else { '0' });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // switches between 64x32 and 128x64 pixels, clearing the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [0; HIRES_SIZE];
+    }
+
+    // sprites are `width` pixels wide, either 8 or 16, with each row
+    // taking up `width / 8` bytes; when several planes are selected,
+    // the sprite holds the data for each of them one after the other.
+    // Returns whether any lit pixel was turned off.
+    pub fn draw(&mut self, x: usize, y: usize, width: usize, sprite: &[u8], planes: u8, clip: bool) -> bool {
+        let mut collision = false;
+        let (w, h) = (self.width(), self.height());
+        let stride = width / 8;
+
+        // the starting position always wraps around, only the
+        // parts of the sprite that go past the edges are clipped
+        let x = x % w;
+        let y = y % h;
+
+        let selected = (0..PLANES)
+            .map(|p| 1_u8 << p)
+            .filter(|bit| planes & bit != 0);
+        let n = sprite.len() / (planes.count_ones() as usize).max(1);
+
+        for (bit, data) in selected.zip(sprite.chunks(n.max(1))) {
+            for (j, row) in data.chunks(stride).enumerate() {
+                if clip && y + j >= h {
+                    break
+                }
+                for i in 0..width {
+                    if clip && x + i >= w {
+                        break
+                    }
+
+                    let yj = (y + j) % h;
+                    let xi = (x + i) % w;
+
+                    if (row[i / 8] & (0x80 >> (i % 8))) != 0 {
+                        if self.pixels[yj*w + xi] & bit != 0 {
+                            collision = true;
+                        }
+                        self.pixels[yj*w + xi] ^= bit;
+                    }
+                }
+            }
+        }
+
+        collision
+    }
+
+    // clears the given planes
+    pub fn clear(&mut self, planes: u8) {
+        for x in self.pixels.iter_mut() {
+            *x &= !planes;
+        }
+    }
+
+    pub fn scroll_down(&mut self, planes: u8, n: usize) {
+        self.map_planes(planes, |scr, w, h, bit| {
+            let n = n.min(h);
+            for y in (0..h).rev() {
+                for x in 0..w {
+                    let px = if y >= n { scr[(y-n)*w + x] & bit } else { 0 };
+                    scr[y*w + x] = (scr[y*w + x] & !bit) | px;
+                }
+            }
+        });
+    }
+
+    pub fn scroll_up(&mut self, planes: u8, n: usize) {
+        self.map_planes(planes, |scr, w, h, bit| {
+            let n = n.min(h);
+            for y in 0..h {
+                for x in 0..w {
+                    let px = if y + n < h { scr[(y+n)*w + x] & bit } else { 0 };
+                    scr[y*w + x] = (scr[y*w + x] & !bit) | px;
+                }
+            }
+        });
+    }
+
+    pub fn scroll_right(&mut self, planes: u8, n: usize) {
+        self.map_planes(planes, |scr, w, _, bit| {
+            let n = n.min(w);
+            for row in scr.chunks_mut(w) {
+                for x in (0..w).rev() {
+                    let px = if x >= n { row[x-n] & bit } else { 0 };
+                    row[x] = (row[x] & !bit) | px;
+                }
+            }
+        });
+    }
+
+    pub fn scroll_left(&mut self, planes: u8, n: usize) {
+        self.map_planes(planes, |scr, w, _, bit| {
+            let n = n.min(w);
+            for row in scr.chunks_mut(w) {
+                for x in 0..w {
+                    let px = if x + n < w { row[x+n] & bit } else { 0 };
+                    row[x] = (row[x] & !bit) | px;
+                }
+            }
+        });
+    }
+
+    // applies `f` to the bits of the given planes in every pixel
+    fn map_planes<F: Fn(&mut [u8], usize, usize, u8)>(&mut self, planes: u8, f: F) {
+        let (w, h) = (self.width(), self.height());
+        for p in 0..PLANES {
+            let bit = 1 << p;
+            if planes & bit != 0 {
+                f(&mut self.pixels[0..w*h], w, h, bit);
+            }
+        }
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collision_and_wrapping() {
+        let mut fb = Framebuffer::new();
+        assert!(!fb.draw(62, 31, 8, &[0xc0, 0xc0], 1, false));
+        assert_eq!(fb.pixel(62, 31), 1);
+        assert_eq!(fb.pixel(63, 31), 1);
+        assert_eq!(fb.pixel(62, 0), 1);
+        assert_eq!(fb.pixel(0, 0), 0);
+
+        // only the bottom row overlaps what's there
+        assert!(fb.draw(62, 0, 8, &[0xc0], 1, false));
+        assert_eq!(fb.pixel(62, 0), 0);
+        assert!(!fb.draw(62, 0, 8, &[0xc0], 1, false));
+    }
+
+    #[test]
+    fn clipping() {
+        let mut fb = Framebuffer::new();
+        fb.draw(60, 30, 8, &[0xff, 0xff, 0xff], 1, true);
+        let lit = fb.pixels.iter().filter(|&&px| px != 0).count();
+        assert_eq!(lit, 8);
+
+        // the start position still wraps
+        let mut fb = Framebuffer::new();
+        fb.draw(64 + 1, 32 + 2, 8, &[0x80], 1, true);
+        assert_eq!(fb.pixel(1, 2), 1);
+    }
+
+    #[test]
+    fn planes() {
+        let mut fb = Framebuffer::new();
+        fb.draw(0, 0, 8, &[0x80, 0xc0], 3, false);
+        assert_eq!(fb.pixel(0, 0), 3);
+        assert_eq!(fb.pixel(1, 0), 2);
+        fb.clear(2);
+        assert_eq!(fb.pixel(0, 0), 1);
+        assert_eq!(fb.pixel(1, 0), 0);
+    }
+}
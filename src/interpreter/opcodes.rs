@@ -0,0 +1,589 @@
+// A test per base CHIP-8 instruction, covering its edge cases: flags,
+// wrapping, skips, the quirks that change it and the memory it touches.
+
+use super::*;
+use super::drivers::input::{Input, Key, KeySet};
+use super::drivers::random::Replay;
+
+// a key held down for the whole test, if any
+struct Held(Option<Key>);
+
+impl Input for Held {
+    fn poll_keyboard(&mut self) -> KeySet {
+        let mut keys = [false; 16];
+        if let Some(k) = self.0 {
+            keys[k as usize] = true;
+        }
+        keys.into()
+    }
+}
+
+fn vm_with(quirks: Quirks, rom: &[u8], regs: &[(usize, u8)]) -> VM {
+    let mut vm = VM::new(quirks);
+    vm.load(rom).unwrap();
+    for &(r, v) in regs {
+        vm.registers_mut()[r] = v;
+    }
+    vm
+}
+
+fn vm(rom: &[u8], regs: &[(usize, u8)]) -> VM {
+    vm_with(Quirks::VIP, rom, regs)
+}
+
+fn step_holding(vm: &mut VM, key: Option<Key>) -> Result<(), VmError> {
+    let mut ctx = Context::with_random((), Held(key), (), Replay::new([0xa5]));
+    vm.step(&mut ctx)
+}
+
+fn step(vm: &mut VM) {
+    step_holding(vm, None).unwrap();
+}
+
+// runs a one instruction program, returning V0 and VF
+fn alu(quirks: Quirks, opcode: u16, x: u8, y: u8) -> (u8, u8) {
+    let mut vm = vm_with(quirks, &opcode.to_be_bytes(), &[(0, x), (1, y), (0xf, 0x55)]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0x202);
+    (vm.registers()[0], vm.registers()[0xf])
+}
+
+#[test]
+fn sys_is_ignored() {
+    let mut vm = vm(&[0x01, 0x23], &[]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0x202);
+}
+
+#[test]
+fn cls() {
+    let mut vm = vm(&[0x00, 0xe0], &[]);
+    vm.fb.pixels[0] = 1;
+    vm.fb.pixels[64 * 32 - 1] = 1;
+    step(&mut vm);
+    assert_eq!(vm.framebuffer(), &Framebuffer::new());
+}
+
+#[test]
+fn call_and_ret() {
+    // CALL 0x206; ...; RET
+    let mut vm = vm(&[0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0xee], &[]);
+    step(&mut vm);
+    assert_eq!((vm.pc(), vm.sp()), (0x206, 1));
+    assert_eq!(vm.call_stack(), &[0x202]);
+    step(&mut vm);
+    assert_eq!((vm.pc(), vm.sp()), (0x202, 0));
+}
+
+#[test]
+fn jp() {
+    let mut vm = vm(&[0x1a, 0xbc], &[]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0xabc);
+}
+
+// the PC after a skip instruction, as it skips or not
+fn skips(rom: [u8; 2], regs: &[(usize, u8)], key: Option<Key>) -> bool {
+    let mut vm = vm(&rom, regs);
+    step_holding(&mut vm, key).unwrap();
+    match vm.pc() {
+        0x204 => true,
+        0x202 => false,
+        pc => panic!("PC ended up at {:03X}", pc),
+    }
+}
+
+#[test]
+fn se_immediate() {
+    assert!(skips([0x30, 0x12], &[(0, 0x12)], None));
+    assert!(!skips([0x30, 0x12], &[(0, 0x13)], None));
+}
+
+#[test]
+fn sne_immediate() {
+    assert!(!skips([0x40, 0x12], &[(0, 0x12)], None));
+    assert!(skips([0x40, 0x12], &[(0, 0x13)], None));
+}
+
+#[test]
+fn se_registers() {
+    assert!(skips([0x50, 0x10], &[(0, 7), (1, 7)], None));
+    assert!(!skips([0x50, 0x10], &[(0, 7), (1, 8)], None));
+}
+
+#[test]
+fn sne_registers() {
+    assert!(!skips([0x90, 0x10], &[(0, 7), (1, 7)], None));
+    assert!(skips([0x90, 0x10], &[(0, 7), (1, 8)], None));
+}
+
+#[test]
+fn skips_over_long_loads() {
+    // SE V0, 0; then F000 NNNN is skipped whole
+    let mut vm = vm(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34], &[]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0x206);
+}
+
+#[test]
+fn ld_immediate() {
+    let mut vm = vm(&[0x6a, 0x42], &[]);
+    step(&mut vm);
+    assert_eq!(vm.registers()[0xa], 0x42);
+}
+
+#[test]
+fn add_immediate_wraps_without_carry() {
+    let mut vm = vm(&[0x70, 0x02], &[(0, 0xff), (0xf, 0x55)]);
+    step(&mut vm);
+    assert_eq!(vm.registers()[0], 0x01);
+    assert_eq!(vm.registers()[0xf], 0x55);
+}
+
+#[test]
+fn ld_register() {
+    assert_eq!(alu(Quirks::VIP, 0x8010, 1, 2), (2, 0x55));
+}
+
+#[test]
+fn logic() {
+    assert_eq!(alu(Quirks::VIP, 0x8011, 0x0c, 0x0a), (0x0e, 0));
+    assert_eq!(alu(Quirks::VIP, 0x8012, 0x0c, 0x0a), (0x08, 0));
+    assert_eq!(alu(Quirks::VIP, 0x8013, 0x0c, 0x0a), (0x06, 0));
+    // only the VIP resets VF
+    assert_eq!(alu(Quirks::SCHIP, 0x8011, 0x0c, 0x0a), (0x0e, 0x55));
+    assert_eq!(alu(Quirks::SCHIP, 0x8012, 0x0c, 0x0a), (0x08, 0x55));
+    assert_eq!(alu(Quirks::SCHIP, 0x8013, 0x0c, 0x0a), (0x06, 0x55));
+}
+
+#[test]
+fn add_registers() {
+    assert_eq!(alu(Quirks::VIP, 0x8014, 1, 2), (3, 0));
+    assert_eq!(alu(Quirks::VIP, 0x8014, 0xff, 1), (0, 1));
+    assert_eq!(alu(Quirks::VIP, 0x8014, 0xff, 0xff), (0xfe, 1));
+}
+
+#[test]
+fn sub() {
+    assert_eq!(alu(Quirks::VIP, 0x8015, 5, 3), (2, 1));
+    // equal values don't borrow
+    assert_eq!(alu(Quirks::VIP, 0x8015, 5, 5), (0, 1));
+    assert_eq!(alu(Quirks::VIP, 0x8015, 3, 5), (0xfe, 0));
+}
+
+#[test]
+fn subn() {
+    assert_eq!(alu(Quirks::VIP, 0x8017, 3, 5), (2, 1));
+    assert_eq!(alu(Quirks::VIP, 0x8017, 5, 5), (0, 1));
+    assert_eq!(alu(Quirks::VIP, 0x8017, 5, 3), (0xfe, 0));
+}
+
+#[test]
+fn shr() {
+    // the VIP shifts VY into VX
+    assert_eq!(alu(Quirks::VIP, 0x8016, 0xf0, 0x03), (0x01, 1));
+    assert_eq!(alu(Quirks::VIP, 0x8016, 0xf1, 0x02), (0x01, 0));
+    // later interpreters shift VX in place
+    assert_eq!(alu(Quirks::SCHIP, 0x8016, 0x03, 0xf0), (0x01, 1));
+}
+
+#[test]
+fn shl() {
+    // VF gets the bit shifted out, not its value
+    assert_eq!(alu(Quirks::VIP, 0x801e, 0x00, 0x81), (0x02, 1));
+    assert_eq!(alu(Quirks::VIP, 0x801e, 0xff, 0x40), (0x80, 0));
+    assert_eq!(alu(Quirks::SCHIP, 0x801e, 0x81, 0x00), (0x02, 1));
+}
+
+#[test]
+fn flags_are_written_after_the_result() {
+    // with VF as X, the flag is what's left
+    let run = |opcode: u16, vf: u8, v1: u8| {
+        let mut vm = vm_with(Quirks::SCHIP, &opcode.to_be_bytes(), &[(0xf, vf), (1, v1)]);
+        step(&mut vm);
+        vm.registers()[0xf]
+    };
+    assert_eq!(run(0x8f14, 0xff, 1), 1);
+    assert_eq!(run(0x8f15, 5, 3), 1);
+    assert_eq!(run(0x8f16, 0x02, 0), 0);
+    assert_eq!(run(0x8f17, 3, 5), 1);
+    assert_eq!(run(0x8f1e, 0x80, 0), 1);
+
+    // with VF as Y, its old value is used
+    assert_eq!(alu(Quirks::VIP, 0x80f4, 1, 0), (0x56, 0));
+}
+
+#[test]
+fn ld_i() {
+    let mut vm = vm(&[0xa1, 0x23], &[]);
+    step(&mut vm);
+    assert_eq!(vm.i(), 0x123);
+}
+
+#[test]
+fn jp_v0() {
+    let mut vm = vm(&[0xb3, 0x00], &[(0, 2), (3, 4)]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0x302);
+
+    // BXNN jumps to XNN + VX on the CHIP-48
+    let mut vm = vm_with(Quirks::CHIP48, &[0xb3, 0x10], &[(0, 2), (3, 4)]);
+    step(&mut vm);
+    assert_eq!(vm.pc(), 0x314);
+}
+
+#[test]
+fn rnd_is_masked() {
+    let mut vm = vm(&[0xc0, 0x0f], &[]);
+    step(&mut vm);
+    assert_eq!(vm.registers()[0], 0xa5 & 0x0f);
+}
+
+#[test]
+fn drw() {
+    // the 0 in the font, at 62,1, twice
+    let mut vm = vm(&[0xd0, 0x15, 0xd0, 0x15], &[(0, 62), (1, 1), (0xf, 0x55)]);
+    step(&mut vm);
+    assert_eq!(vm.registers()[0xf], 0);
+    assert_eq!(vm.framebuffer().pixel(62, 1), 1);
+    assert_eq!(vm.framebuffer().pixel(63, 2), 0);
+    // clipped at the right edge
+    assert_eq!(vm.framebuffer().pixel(0, 1), 0);
+
+    step(&mut vm);
+    assert_eq!(vm.registers()[0xf], 1);
+    assert_eq!(vm.framebuffer(), &Framebuffer::new());
+}
+
+#[test]
+fn skp() {
+    assert!(skips([0xe0, 0x9e], &[(0, 5)], Some(Key::Five)));
+    assert!(!skips([0xe0, 0x9e], &[(0, 5)], Some(Key::Six)));
+    assert!(!skips([0xe0, 0x9e], &[(0, 5)], None));
+}
+
+#[test]
+fn sknp() {
+    assert!(!skips([0xe0, 0xa1], &[(0, 5)], Some(Key::Five)));
+    assert!(skips([0xe0, 0xa1], &[(0, 5)], Some(Key::Six)));
+    assert!(skips([0xe0, 0xa1], &[(0, 5)], None));
+}
+
+#[test]
+fn delay_timer() {
+    // LD DT, V0; LD V1, DT
+    let mut vm = vm(&[0xf0, 0x15, 0xf1, 0x07], &[(0, 9)]);
+    step(&mut vm);
+    assert_eq!(vm.dt(), 9);
+    step(&mut vm);
+    assert_eq!(vm.registers()[1], 9);
+}
+
+#[test]
+fn ld_key() {
+    // the key has to go down and come back up
+    let mut vm = vm(&[0xf3, 0x0a], &[]);
+    step_holding(&mut vm, None).unwrap();
+    assert_eq!(vm.pc(), 0x200);
+    step_holding(&mut vm, Some(Key::C)).unwrap();
+    step_holding(&mut vm, Some(Key::C)).unwrap();
+    assert_eq!(vm.registers()[3], 0);
+    assert_eq!(vm.pc(), 0x200);
+    step_holding(&mut vm, None).unwrap();
+    assert_eq!(vm.registers()[3], 0xc);
+    assert_eq!(vm.pc(), 0x202);
+}
+
+#[test]
+fn ld_key_keeps_the_timers_running() {
+    let mut vm = vm(&[0xf3, 0x0a], &[]);
+    vm.reg_dt = 10;
+    vm.reg_snd = 10;
+    let mut ctx = Context::new((), (), ());
+    for _ in 0..3 {
+        vm.run_frame(&mut ctx).unwrap();
+    }
+    assert_eq!(vm.pc(), 0x200);
+    assert_eq!(vm.dt(), 7);
+    assert_eq!(vm.st(), 7);
+}
+
+#[test]
+fn sound_timer() {
+    let mut vm = vm(&[0xf0, 0x18], &[(0, 9)]);
+    step(&mut vm);
+    assert_eq!(vm.st(), 9);
+}
+
+#[test]
+fn add_i() {
+    let mut vm = vm(&[0xf0, 0x1e], &[(0, 1), (0xf, 0x55)]);
+    vm.reg_i = 0xfff;
+    step(&mut vm);
+    assert_eq!(vm.i(), 0x1000);
+    assert_eq!(vm.registers()[0xf], 0x55);
+}
+
+#[test]
+fn font_digit_of_vx() {
+    // the digit is in V3, not 3
+    let mut a = vm(&[0xf3, 0x29], &[(3, 0xa)]);
+    step(&mut a);
+    assert_eq!(a.i(), 50);
+    assert_eq!(&a.ram()[50..55], &[0xf0, 0x90, 0xf0, 0x90, 0x90]);
+
+    // only the low nibble counts
+    let mut two = vm(&[0xf3, 0x29], &[(3, 0x12)]);
+    step(&mut two);
+    assert_eq!(two.i(), 10);
+}
+
+#[test]
+fn bcd() {
+    let bcd = |x: u8| {
+        let mut vm = vm(&[0xf4, 0x33], &[(4, x)]);
+        vm.reg_i = 0x300;
+        step(&mut vm);
+        assert_eq!((vm.i(), vm.pc()), (0x300, 0x202));
+        [vm.ram()[0x300], vm.ram()[0x301], vm.ram()[0x302]]
+    };
+    assert_eq!(bcd(254), [2, 5, 4]);
+    assert_eq!(bcd(70), [0, 7, 0]);
+    assert_eq!(bcd(7), [0, 0, 7]);
+    assert_eq!(bcd(0), [0, 0, 0]);
+
+    let mut vm = vm(&[0xf4, 0x33], &[]);
+    vm.reg_i = 0xfffe;
+    assert!(matches!(
+        step_holding(&mut vm, None),
+        Err(VmError::MemoryOutOfBounds { addr: 0xfffe, len: 3, .. })
+    ));
+}
+
+#[test]
+fn store_registers() {
+    let store = |quirks: Quirks, x: u8| {
+        let regs: Vec<(usize, u8)> = (0..16).map(|r| (r, 0x10 + r as u8)).collect();
+        let mut vm = vm_with(quirks, &[0xf0 | x, 0x55], &regs);
+        vm.reg_i = 0x300;
+        step(&mut vm);
+        assert_eq!(vm.pc(), 0x202);
+        (vm.ram()[0x300..0x311].to_vec(), vm.i())
+    };
+
+    // V0 to V2, inclusive
+    let (ram, i) = store(Quirks::VIP, 2);
+    assert_eq!(&ram[..4], &[0x10, 0x11, 0x12, 0]);
+    assert_eq!(i, 0x303);
+    let (ram, _) = store(Quirks::VIP, 0);
+    assert_eq!(&ram[..2], &[0x10, 0]);
+    let (ram, i) = store(Quirks::VIP, 15);
+    assert_eq!(&ram[15..17], &[0x1f, 0]);
+    assert_eq!(i, 0x310);
+
+    assert_eq!(store(Quirks::CHIP48, 2).1, 0x302);
+    assert_eq!(store(Quirks::SCHIP, 2).1, 0x300);
+}
+
+#[test]
+fn load_registers() {
+    let load = |quirks: Quirks, x: u8| {
+        let mut vm = vm_with(quirks, &[0xf0 | x, 0x65], &[]);
+        vm.reg_i = 0x300;
+        for k in 0..16 {
+            vm.ram_mut()[0x300 + k] = 0x20 + k as u8;
+        }
+        step(&mut vm);
+        assert_eq!(vm.pc(), 0x202);
+        (*vm.registers(), vm.i())
+    };
+
+    let (regs, i) = load(Quirks::VIP, 2);
+    assert_eq!(&regs[..4], &[0x20, 0x21, 0x22, 0]);
+    assert_eq!(i, 0x303);
+    let (regs, _) = load(Quirks::VIP, 15);
+    assert_eq!(regs[15], 0x2f);
+
+    assert_eq!(load(Quirks::CHIP48, 2).1, 0x302);
+    assert_eq!(load(Quirks::SCHIP, 2).1, 0x300);
+}
+
+#[test]
+fn load_and_store_stay_in_memory() {
+    for opcode in [0xf155_u16, 0xf165] {
+        let mut vm = vm(&opcode.to_be_bytes(), &[]);
+        vm.reg_i = 0xffff;
+        assert!(matches!(
+            step_holding(&mut vm, None),
+            Err(VmError::MemoryOutOfBounds { addr: 0xffff, len: 2, .. })
+        ));
+    }
+
+    // up to the last byte is fine
+    let mut vm = vm(&[0xf1, 0x55], &[(0, 1), (1, 2)]);
+    vm.reg_i = 0xfffe;
+    step(&mut vm);
+    assert_eq!(&vm.ram()[0xfffe..], &[1, 2]);
+    assert_eq!(vm.i(), 0);
+}
+
+// SUPER-CHIP, where it departs from SCHIP 1.1, as noted in quirks.rs
+
+#[test]
+fn flags_hold_all_sixteen_registers() {
+    // LD R, VF; LD VF, R, with the registers cleared in between
+    let mut vm = vm_with(Quirks::SCHIP, &[0xff, 0x75, 0xff, 0x85], &[(0xf, 0x42), (8, 7)]);
+    step(&mut vm);
+    vm.registers_mut().fill(0);
+    step(&mut vm);
+    assert_eq!((vm.registers()[8], vm.registers()[0xf]), (7, 0x42));
+}
+
+#[test]
+fn big_sprites_in_low_resolution() {
+    // DRW V0, V0, 0 with a 16x16 sprite of full rows
+    let mut vm = vm_with(Quirks::SCHIP, &[0xd0, 0x00], &[]);
+    vm.reg_i = 0x300;
+    vm.ram[0x300..0x320].fill(0xff);
+    step(&mut vm);
+    assert!(!vm.fb.hires);
+    let lit: Vec<usize> = vm.fb.rows().map(|row| row.iter().filter(|&&px| px != 0).count()).collect();
+    assert_eq!(&lit[..16], &[16; 16]);
+    assert_eq!(lit[16], 0);
+}
+
+#[test]
+fn scrolls_in_low_resolution() {
+    let scrolled = |opcode: u16| {
+        let mut vm = vm_with(Quirks::SCHIP, &opcode.to_be_bytes(), &[]);
+        vm.fb.pixels[10 * 64 + 10] = 1;
+        step(&mut vm);
+        let at = vm.fb.pixels.iter().position(|&px| px != 0).unwrap();
+        (at % 64, at / 64)
+    };
+    // by screen pixels, not high resolution ones
+    assert_eq!(scrolled(0x00c3), (10, 13));
+    assert_eq!(scrolled(0x00fb), (14, 10));
+    assert_eq!(scrolled(0x00fc), (6, 10));
+}
+
+// XO-CHIP
+
+#[test]
+fn store_register_range() {
+    // SAVE V2 - V4, then the same backwards, V4 - V2
+    let regs = [(2, 0x22), (3, 0x33), (4, 0x44), (5, 0x55)];
+    let mut vm = vm_with(Quirks::XOCHIP, &[0x52, 0x42], &regs);
+    vm.reg_i = 0x300;
+    step(&mut vm);
+    assert_eq!(&vm.ram()[0x300..0x304], &[0x22, 0x33, 0x44, 0]);
+    // I is left as it is
+    assert_eq!(vm.i(), 0x300);
+
+    let mut vm = vm_with(Quirks::XOCHIP, &[0x54, 0x22], &regs);
+    vm.reg_i = 0x300;
+    step(&mut vm);
+    assert_eq!(&vm.ram()[0x300..0x304], &[0x44, 0x33, 0x22, 0]);
+}
+
+#[test]
+fn load_register_range() {
+    // LOAD V2 - V4, then V4 - V2
+    let mut vm = vm_with(Quirks::XOCHIP, &[0x52, 0x43], &[]);
+    vm.reg_i = 0x300;
+    vm.ram[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+    step(&mut vm);
+    assert_eq!(&vm.registers()[1..6], &[0, 1, 2, 3, 0]);
+
+    let mut vm = vm_with(Quirks::XOCHIP, &[0x54, 0x23], &[]);
+    vm.reg_i = 0x300;
+    vm.ram[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+    step(&mut vm);
+    assert_eq!(&vm.registers()[1..6], &[0, 3, 2, 1, 0]);
+    assert_eq!(vm.i(), 0x300);
+}
+
+#[test]
+fn register_ranges_stay_in_memory() {
+    // three registers from 0xfffe would end past 64 KiB
+    for opcode in [0x5022_u16, 0x5023, 0x5202, 0x5203] {
+        let mut vm = vm_with(Quirks::XOCHIP, &opcode.to_be_bytes(), &[]);
+        vm.reg_i = 0xfffe;
+        assert!(matches!(
+            step_holding(&mut vm, None),
+            Err(VmError::MemoryOutOfBounds { addr: 0xfffe, len: 3, .. })
+        ), "{:04X}", opcode);
+        assert_eq!(vm.pc(), 0x200);
+    }
+
+    // two fit
+    let mut vm = vm_with(Quirks::XOCHIP, &[0x50, 0x12], &[(0, 7), (1, 8)]);
+    vm.reg_i = 0xfffe;
+    step(&mut vm);
+    assert_eq!(&vm.ram()[0xfffe..], &[7, 8]);
+}
+
+#[test]
+fn scroll_up_on_the_selected_plane() {
+    // PLANE 2; SCROLL-UP 3
+    let mut vm = vm_with(Quirks::XOCHIP, &[0xf2, 0x01, 0x00, 0xd3], &[]);
+    vm.fb.pixels[10 * 64 + 5] = 3;
+    step(&mut vm);
+    step(&mut vm);
+    // plane 1 stays where it was, plane 2 moves up 3 rows
+    assert_eq!(vm.fb.pixel(5, 10), 1);
+    assert_eq!(vm.fb.pixel(5, 7), 2);
+    assert_eq!(vm.fb.pixels.iter().filter(|&&px| px != 0).count(), 2);
+}
+
+// what the VM told the sound driver last
+#[derive(Default)]
+struct Speaker {
+    pattern: Option<[u8; 16]>,
+    pitch: Option<u8>,
+}
+
+impl Sound for Speaker {
+    fn beep_start(&mut self) {}
+    fn beep_end(&mut self) {}
+
+    fn set_pattern(&mut self, pattern: &[u8; 16]) {
+        self.pattern = Some(*pattern);
+    }
+
+    fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = Some(pitch);
+    }
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    // AUDIO; PITCH V3
+    let mut vm = vm_with(Quirks::XOCHIP, &[0xf0, 0x02, 0xf3, 0x3a], &[(3, 112)]);
+    vm.reg_i = 0x300;
+    for (k, b) in vm.ram[0x300..0x310].iter_mut().enumerate() {
+        *b = k as u8 * 17;
+    }
+    let mut ctx = Context::with_random((), Held(None), Speaker::default(), Replay::new([0]));
+
+    vm.step(&mut ctx).unwrap();
+    let pattern: Vec<u8> = (0..16).map(|k| k * 17).collect();
+    assert_eq!(&vm.pattern[..], &pattern[..]);
+    assert_eq!(ctx.sound_mut().pattern.map(|p| p.to_vec()), Some(pattern));
+    assert_eq!(ctx.sound_mut().pitch, None);
+
+    vm.step(&mut ctx).unwrap();
+    assert_eq!(vm.pitch, 112);
+    assert_eq!(ctx.sound_mut().pitch, Some(112));
+    assert_eq!(vm.pc(), 0x204);
+}
+
+#[test]
+fn audio_pattern_stays_in_memory() {
+    let mut vm = vm_with(Quirks::XOCHIP, &[0xf0, 0x02], &[]);
+    vm.reg_i = 0xfff8;
+    assert!(matches!(
+        step_holding(&mut vm, None),
+        Err(VmError::MemoryOutOfBounds { addr: 0xfff8, len: 16, .. })
+    ));
+}
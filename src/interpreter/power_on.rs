@@ -0,0 +1,96 @@
+use super::drivers::random::{Random, Xorshift};
+
+// what memory, the registers and the stack hold when the machine is
+// switched on, before anything is loaded; programs that read memory
+// they never wrote see this
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum PowerOnPattern {
+    #[default]
+    Zero,
+    // random bytes from a seed, the same ones every time
+    Random(u64),
+    // bytes alternating between 0x00 and 0xFF, roughly how the
+    // COSMAC VIP's RAM comes up; its registers and stack live in
+    // RAM, so they get the same
+    Vip,
+}
+
+impl PowerOnPattern {
+    // zero, vip, or random:SEED
+    pub fn parse(name: &str) -> Option<PowerOnPattern> {
+        if name.eq_ignore_ascii_case("zero") {
+            return Some(PowerOnPattern::Zero)
+        }
+        if name.eq_ignore_ascii_case("vip") {
+            return Some(PowerOnPattern::Vip)
+        }
+        let seed = prefixed(name, "random:")?;
+        let seed = match prefixed(seed, "0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+            None => seed.parse().ok()?,
+        };
+        Some(PowerOnPattern::Random(seed))
+    }
+
+    pub fn fill(&self, ram: &mut [u8], registers: &mut [u8; 16], stack: &mut [u16; 16]) {
+        match *self {
+            PowerOnPattern::Zero => {
+                ram.fill(0);
+                registers.fill(0);
+                stack.fill(0);
+            },
+            PowerOnPattern::Random(seed) => {
+                let mut rng = Xorshift::new(seed);
+                ram.iter_mut().chain(registers.iter_mut()).for_each(|b| *b = rng.byte());
+                stack.iter_mut().for_each(|w| *w = u16::from_be_bytes([rng.byte(), rng.byte()]));
+            },
+            PowerOnPattern::Vip => {
+                let alternate = |i: usize| [0x00, 0xff][i % 2];
+                ram.iter_mut().enumerate().for_each(|(i, b)| *b = alternate(i));
+                registers.iter_mut().enumerate().for_each(|(i, b)| *b = alternate(i));
+                stack.fill(0x00ff);
+            },
+        }
+    }
+}
+
+// what follows `prefix`, in any case, at the start of `s`
+fn prefixed<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    s.get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(PowerOnPattern::parse("zero"), Some(PowerOnPattern::Zero));
+        assert_eq!(PowerOnPattern::parse("VIP"), Some(PowerOnPattern::Vip));
+        assert_eq!(PowerOnPattern::parse("random:42"), Some(PowerOnPattern::Random(42)));
+        assert_eq!(PowerOnPattern::parse("random:0xc8"), Some(PowerOnPattern::Random(0xc8)));
+        assert_eq!(PowerOnPattern::parse("random"), None);
+        assert_eq!(PowerOnPattern::parse("ones"), None);
+    }
+
+    #[test]
+    fn patterns() {
+        let fill = |pattern: PowerOnPattern| {
+            let (mut ram, mut regs, mut stack) = ([0x11; 8], [0x22; 16], [0x3333; 16]);
+            pattern.fill(&mut ram, &mut regs, &mut stack);
+            (ram, regs, stack)
+        };
+
+        assert_eq!(fill(PowerOnPattern::Zero), ([0; 8], [0; 16], [0; 16]));
+
+        let (ram, regs, _) = fill(PowerOnPattern::Vip);
+        assert_eq!(ram, [0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff]);
+        assert_eq!(&regs[..2], &[0x00, 0xff]);
+
+        // the same seed gives the same machine
+        assert_eq!(fill(PowerOnPattern::Random(7)), fill(PowerOnPattern::Random(7)));
+        assert_ne!(fill(PowerOnPattern::Random(7)), fill(PowerOnPattern::Random(8)));
+    }
+}
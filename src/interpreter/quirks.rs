@@ -0,0 +1,100 @@
+// behaviours that differ between CHIP-8 implementations; see:
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // what FX55/FX65 do to I after the transfer
+    pub load_store: LoadStore,
+    // BNNN jumps to XNN + VX, instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1/8XY2/8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // sprites are cut off at the screen edges, instead of wrapping around
+    pub clip_sprites: bool,
+    // DXYN waits for the next vertical blank, so at most one
+    // sprite is drawn per frame
+    pub display_wait: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LoadStore {
+    // I is left pointing past the last register, I += X + 1
+    Increment,
+    // I += X, as on the original CHIP-48
+    IncrementByX,
+    // I is unchanged
+    Unchanged,
+}
+
+impl Quirks {
+    pub const VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store: LoadStore::Increment,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        clip_sprites: true,
+        display_wait: true,
+    };
+
+    pub const CHIP48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store: LoadStore::IncrementByX,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+    };
+
+    // SUPER-CHIP as programs written for it today expect, which isn't
+    // quite the HP48's SCHIP 1.1 in ways that no quirk covers:
+    // - FX75/FX85 save and restore any of the 16 registers, as on
+    //   XO-CHIP, where SCHIP 1.1 only has 8 flags and X up to 7
+    // - in low resolution, DXY0 draws a 16x16 sprite, where SCHIP 1.1
+    //   draws one 8 pixels wide
+    // - in low resolution, 00CN, 00FB and 00FC scroll by screen pixels,
+    //   where SCHIP 1.1 counts high resolution ones and so moves half
+    //   as far
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store: LoadStore::Unchanged,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        clip_sprites: true,
+        display_wait: false,
+    };
+
+    pub const XOCHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store: LoadStore::Increment,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        clip_sprites: false,
+        display_wait: false,
+    };
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        PRESETS.iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|&(_, quirks)| quirks)
+    }
+}
+
+// the names Quirks::preset knows, in any case
+const PRESETS: [(&str, Quirks); 9] = [
+    ("vip", Quirks::VIP),
+    ("chip8", Quirks::VIP),
+    ("chip-8", Quirks::VIP),
+    ("chip48", Quirks::CHIP48),
+    ("chip-48", Quirks::CHIP48),
+    ("schip", Quirks::SCHIP),
+    ("superchip", Quirks::SCHIP),
+    ("xochip", Quirks::XOCHIP),
+    ("xo-chip", Quirks::XOCHIP),
+];
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::VIP
+    }
+}
@@ -0,0 +1,490 @@
+// Save state format, version 3. All integers are big endian.
+//
+//   offset  size   contents
+//   0       4      magic, "C8SS"
+//   4       2      format version
+//   6       1      number of registers, 16
+//   7       1      stack depth, 16
+//   8       4      RAM size in bytes
+//   12      4      framebuffer size in bytes
+//   16      16     V0 to VF
+//   32      32     stack, 16 return addresses
+//   64      1      SP
+//   65      2      PC
+//   67      2      I
+//   69      1      DT
+//   70      1      ST
+//   71      4      cycles run since the timers were last decremented
+//   75      1      halted flag
+//   76      1      selected XO-CHIP planes
+//   77      1      XO-CHIP pitch
+//   78      16     SUPER-CHIP RPL flags
+//   94      16     XO-CHIP audio pattern
+//   110     1      high resolution flag
+//   111     2      entry point, where the program was loaded and
+//                  where a reset starts it again
+//   113     ...    RAM, followed by the framebuffer
+//
+// Version 2 is the same, without the entry point, and version 1 also
+// has a single byte cycle count, moving everything after it 3 bytes
+// up; both can still be loaded, starting programs at 0x200.
+//
+// The framebuffer holds one byte per pixel of the 128x64 screen, row
+// by row, with a bit per XO-CHIP plane; in low resolution only the
+// first 64x32 are used.
+
+use std::fmt;
+use std::error;
+use std::io::{self, Read, Write};
+
+use super::VM;
+use super::{RAM_SIZE, PROGRAM_START};
+use super::drivers::{Context, Display, Sound};
+use super::framebuffer::{Framebuffer, HIRES_SIZE, PLANES};
+
+pub const MAGIC: [u8; 4] = *b"C8SS";
+pub const VERSION: u16 = 3;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    SizeMismatch {
+        what: &'static str,
+        expected: u32,
+        found: u32,
+    },
+    // the state holds values the VM can't be in
+    BadStackPointer(u8),
+    BadPlanes(u8),
+    BadProgramCounter(u16),
+    BadEntryPoint(u16),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "{}", e),
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported save state version {}", v)
+            },
+            SaveStateError::SizeMismatch { what, expected, found } => {
+                write!(f, "{} size is {}, expected {}", what, found, expected)
+            },
+            SaveStateError::BadStackPointer(sp) => {
+                write!(f, "stack pointer {} is past the end of the stack", sp)
+            },
+            SaveStateError::BadPlanes(planes) => {
+                write!(f, "no such planes selected: 0x{:02X}", planes)
+            },
+            SaveStateError::BadProgramCounter(pc) => {
+                write!(f, "program counter 0x{:04X} is past the end of memory", pc)
+            },
+            SaveStateError::BadEntryPoint(addr) => {
+                write!(f, "entry point 0x{:04X} is past the end of memory", addr)
+            },
+        }
+    }
+}
+
+impl error::Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SaveStateError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(e: io::Error) -> Self {
+        SaveStateError::Io(e)
+    }
+}
+
+impl VM {
+    pub fn save_state<W: Write>(&self, mut out: W) -> Result<(), SaveStateError> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_be_bytes())?;
+        out.write_all(&[16, 16])?;
+        out.write_all(&(RAM_SIZE as u32).to_be_bytes())?;
+        out.write_all(&(HIRES_SIZE as u32).to_be_bytes())?;
+
+        out.write_all(self.registers())?;
+        for ret in self.stack().iter() {
+            out.write_all(&ret.to_be_bytes())?;
+        }
+        out.write_all(&[self.reg_sp])?;
+        out.write_all(&self.reg_pc.to_be_bytes())?;
+        out.write_all(&self.reg_i.to_be_bytes())?;
+        out.write_all(&[self.reg_dt, self.reg_snd])?;
+        out.write_all(&self.cycles.to_be_bytes())?;
+        out.write_all(&[self.halted as u8, self.planes, self.pitch])?;
+        out.write_all(&self.rpl)?;
+        out.write_all(&self.pattern)?;
+        out.write_all(&[self.fb.hires as u8])?;
+        out.write_all(&self.entry.to_be_bytes())?;
+
+        out.write_all(self.ram())?;
+        out.write_all(&self.fb.pixels)?;
+        out.flush()?;
+
+        Ok(())
+    }
+
+    // the VM is only modified once the whole state has been read
+    pub fn load_state<D, I, S, R, In>(&mut self, ctx: &mut Context<D, I, S, R>, mut input: In) -> Result<(), SaveStateError>
+    where
+        D: Display,
+        S: Sound,
+        In: Read,
+    {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SaveStateError::BadMagic)
+        }
+
+        let version = read_u16(&mut input)?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(SaveStateError::UnsupportedVersion(version))
+        }
+
+        let mut sizes = [0; 2];
+        input.read_exact(&mut sizes)?;
+        check_size("register file", 16, sizes[0] as u32)?;
+        check_size("stack", 16, sizes[1] as u32)?;
+        check_size("RAM", RAM_SIZE as u32, read_u32(&mut input)?)?;
+        check_size("framebuffer", HIRES_SIZE as u32, read_u32(&mut input)?)?;
+
+        let mut registers = [0; 16];
+        input.read_exact(&mut registers)?;
+        let mut stack = [0; 16];
+        for ret in stack.iter_mut() {
+            *ret = read_u16(&mut input)?;
+        }
+        let sp = read_u8(&mut input)?;
+        let pc = read_u16(&mut input)?;
+        let i = read_u16(&mut input)?;
+        let mut timers = [0; 2];
+        input.read_exact(&mut timers)?;
+        let cycles = match version {
+            1 => read_u8(&mut input)? as u32,
+            _ => read_u32(&mut input)?,
+        };
+        let mut misc = [0; 3];
+        input.read_exact(&mut misc)?;
+        let mut rpl = [0; 16];
+        input.read_exact(&mut rpl)?;
+        let mut pattern = [0; 16];
+        input.read_exact(&mut pattern)?;
+
+        let mut fb = Framebuffer::new();
+        fb.hires = read_u8(&mut input)? != 0;
+        let entry = match version {
+            1 | 2 => PROGRAM_START,
+            _ => read_u16(&mut input)?,
+        };
+
+        if sp as usize > stack.len() {
+            return Err(SaveStateError::BadStackPointer(sp))
+        }
+        if misc[1] & !((1 << PLANES) - 1) != 0 {
+            return Err(SaveStateError::BadPlanes(misc[1]))
+        }
+        // the same bounds the VM fetches instructions within
+        if !fetchable(pc) {
+            return Err(SaveStateError::BadProgramCounter(pc))
+        }
+        if !fetchable(entry) {
+            return Err(SaveStateError::BadEntryPoint(entry))
+        }
+
+        // read RAM into a spare buffer, so a truncated file
+        // leaves the machine as it was
+        let mut ram = vec![0; RAM_SIZE];
+        input.read_exact(&mut ram)?;
+        input.read_exact(&mut fb.pixels)?;
+
+        *self.registers_mut() = registers;
+        *self.stack_mut() = stack;
+        self.ram_mut().copy_from_slice(&ram);
+        self.reg_sp = sp;
+        self.reg_pc = pc;
+        self.entry = entry;
+        self.reg_i = i;
+        self.reg_dt = timers[0];
+        self.reg_snd = timers[1];
+        self.cycles = cycles;
+        self.halted = misc[0] != 0;
+        self.planes = misc[1];
+        self.pitch = misc[2];
+        self.rpl = rpl;
+        self.pattern = pattern;
+
+        self.fb = fb;
+        self.dirty = false;
+        // what the VM was in the middle of isn't saved, so it starts
+        // over, as after a reset: FX0A waits for a new key press, and
+        // a frame cut short by DXYN runs in full
+        self.key_down = None;
+        self.vblank_wait = false;
+        ctx.present(&self.fb);
+        ctx.set_pattern(&self.pattern);
+        ctx.set_pitch(self.pitch);
+        if self.reg_snd == 0 {
+            ctx.beep_end();
+        } else {
+            ctx.beep_start();
+        }
+
+        Ok(())
+    }
+}
+
+fn check_size(what: &'static str, expected: u32, found: u32) -> Result<(), SaveStateError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(SaveStateError::SizeMismatch { what, expected, found })
+    }
+}
+
+fn fetchable(addr: u16) -> bool {
+    (addr as usize) + 2 < RAM_SIZE
+}
+
+fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut b = [0; 1];
+    input.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut b = [0; 2];
+    input.read_exact(&mut b)?;
+    Ok(u16::from_be_bytes(b))
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    input.read_exact(&mut b)?;
+    Ok(u32::from_be_bytes(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::quirks::Quirks;
+    use crate::interpreter::drivers::Input;
+    use crate::interpreter::drivers::input::KeySet;
+
+    #[test]
+    fn round_trip() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load(include_bytes!("../../roms/maze.rom")).unwrap();
+        *vm.registers_mut() = [7; 16];
+        vm.fb.hires = true;
+        vm.fb.pixels[100] = 3;
+        let mut ctx = Context::new((), (), ());
+        for _ in 0..50 {
+            vm.step(&mut ctx).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+        assert_eq!(buf.len(), 113 + RAM_SIZE + HIRES_SIZE);
+
+        let mut other = VM::new(Quirks::default());
+        let mut other_ctx = Context::new((), (), ());
+        other.load_state(&mut other_ctx, &buf[..]).unwrap();
+
+        let mut again = Vec::new();
+        other.save_state(&mut again).unwrap();
+        assert_eq!(buf, again);
+        assert_eq!(other.pc(), vm.pc());
+        assert_eq!(other.framebuffer(), vm.framebuffer());
+    }
+
+    #[test]
+    fn loads_older_versions() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load(include_bytes!("../../roms/maze.rom")).unwrap();
+        let mut ctx = Context::new((), (), ());
+        for _ in 0..5 {
+            vm.step(&mut ctx).unwrap();
+        }
+        let mut v3 = Vec::new();
+        vm.save_state(&mut v3).unwrap();
+
+        // the entry point goes, and then the cycle count shrinks
+        // back to one byte
+        let mut v2 = v3.clone();
+        v2[5] = 2;
+        v2.drain(111..113);
+        let mut v1 = v2.clone();
+        v1[5] = 1;
+        v1.splice(71..75, [v2[74]]);
+
+        for old in [v2, v1].iter() {
+            let mut other = VM::new(Quirks::default());
+            other.load_state(&mut ctx, &old[..]).unwrap();
+            let mut again = Vec::new();
+            other.save_state(&mut again).unwrap();
+            assert_eq!(again, v3);
+        }
+    }
+
+    #[test]
+    fn keeps_the_entry_point() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load_at(0x600, [0x12, 0x02, 0x16, 0x02]).unwrap();
+        let mut ctx = Context::new((), (), ());
+        vm.step(&mut ctx).unwrap();
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        let mut other = VM::new(Quirks::default());
+        other.load_state(&mut ctx, &buf[..]).unwrap();
+        assert_eq!(other.pc(), 0x202);
+        other.reset();
+        assert_eq!(other.pc(), 0x600);
+    }
+
+    // a keypad with `0` down, or nothing at all
+    struct Keypad(bool);
+
+    impl Input for Keypad {
+        fn poll_keyboard(&mut self) -> KeySet {
+            let mut keys = [false; 16];
+            keys[0] = self.0;
+            KeySet::from(keys)
+        }
+    }
+
+    #[test]
+    fn forgets_the_key_down() {
+        // LD V1, K, saved before any key is pressed
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0xf1, 0x0a]).unwrap();
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        // the key goes down, then the state is loaded
+        let mut ctx = Context::new((), Keypad(true), ());
+        vm.step(&mut ctx).unwrap();
+        assert!(vm.key_down().is_some());
+        vm.load_state(&mut ctx, &buf[..]).unwrap();
+        assert_eq!(vm.key_down(), None);
+
+        // letting go of it doesn't count as a press
+        let mut ctx = Context::new((), Keypad(false), ());
+        vm.step(&mut ctx).unwrap();
+        assert_eq!(vm.pc(), 0x200);
+    }
+
+    #[test]
+    fn forgets_the_vblank_wait() {
+        let mut vm = VM::new(Quirks::VIP);
+        vm.load([0x12, 0x00]).unwrap();
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        let mut ctx = Context::new((), (), ());
+        vm.vblank_wait = true;
+        vm.load_state(&mut ctx, &buf[..]).unwrap();
+        assert!(!vm.vblank_wait);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0x12, 0x00]).unwrap();
+        let mut ctx = Context::new((), (), ());
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert!(matches!(vm.load_state(&mut ctx, &bad[..]), Err(SaveStateError::BadMagic)));
+
+        let mut bad = buf.clone();
+        bad[5] = 4;
+        assert!(matches!(
+            vm.load_state(&mut ctx, &bad[..]),
+            Err(SaveStateError::UnsupportedVersion(4))
+        ));
+
+        let mut bad = buf.clone();
+        bad[8..12].copy_from_slice(&4096_u32.to_be_bytes());
+        assert!(matches!(
+            vm.load_state(&mut ctx, &bad[..]),
+            Err(SaveStateError::SizeMismatch { what: "RAM", .. })
+        ));
+
+        let truncated = &buf[..buf.len() - 1];
+        assert!(matches!(vm.load_state(&mut ctx, truncated), Err(SaveStateError::Io(_))));
+    }
+
+    #[test]
+    fn rejects_a_bad_stack_pointer() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0x12, 0x00]).unwrap();
+        let mut ctx = Context::new((), (), ());
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        buf[64] = 17;
+        assert!(matches!(
+            vm.load_state(&mut ctx, &buf[..]),
+            Err(SaveStateError::BadStackPointer(17))
+        ));
+        buf[64] = 16;
+        assert!(vm.load_state(&mut ctx, &buf[..]).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_planes() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0x12, 0x00]).unwrap();
+        let mut ctx = Context::new((), (), ());
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        buf[76] = 4;
+        assert!(matches!(
+            vm.load_state(&mut ctx, &buf[..]),
+            Err(SaveStateError::BadPlanes(4))
+        ));
+        buf[76] = 3;
+        assert!(vm.load_state(&mut ctx, &buf[..]).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_bad_program_counter() {
+        let mut vm = VM::new(Quirks::default());
+        vm.load([0x12, 0x00]).unwrap();
+        let mut ctx = Context::new((), (), ());
+        let mut buf = Vec::new();
+        vm.save_state(&mut buf).unwrap();
+
+        let mut bad = buf.clone();
+        bad[65..67].copy_from_slice(&0xfffe_u16.to_be_bytes());
+        assert!(matches!(
+            vm.load_state(&mut ctx, &bad[..]),
+            Err(SaveStateError::BadProgramCounter(0xfffe))
+        ));
+
+        let mut bad = buf.clone();
+        bad[111..113].copy_from_slice(&0xffff_u16.to_be_bytes());
+        assert!(matches!(
+            vm.load_state(&mut ctx, &bad[..]),
+            Err(SaveStateError::BadEntryPoint(0xffff))
+        ));
+
+        // nothing was changed by the rejected states
+        assert_eq!(vm.pc(), 0x200);
+    }
+}
@@ -0,0 +1,154 @@
+use core::time::Duration;
+
+use super::DELAY_TICK_FREQ;
+
+// after a stall, at most this many frames are run back to
+// back to catch up, and the rest are dropped
+pub const MAX_CATCH_UP: u32 = 4;
+
+// the host's sense of time: how long it's been since some fixed
+// point, which never goes backwards, and a way to wait; on a board
+// without std, a hardware timer and a busy loop will do
+pub trait Clock {
+    fn now(&mut self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+// the standard library's monotonic clock, counting from its creation
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub struct StdClock(std::time::Instant);
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub fn new() -> Self {
+        StdClock(std::time::Instant::now())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&mut self) -> Duration {
+        self.0.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+// paces VM::run_frame to 60 Hz of the host's time; frames are due at
+// exact multiples of 1/60 s from the start, so the timers don't drift
+pub struct Scheduler<C: Clock> {
+    clock: C,
+    start: Duration,
+    frames: u64,
+    max_catch_up: u32,
+}
+
+#[cfg(feature = "std")]
+impl Scheduler<StdClock> {
+    pub fn new() -> Self {
+        Self::with_max_catch_up(MAX_CATCH_UP)
+    }
+
+    pub fn with_max_catch_up(max_catch_up: u32) -> Self {
+        Self::with_clock(StdClock::new(), max_catch_up)
+    }
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn with_clock(mut clock: C, max_catch_up: u32) -> Self {
+        Scheduler {
+            start: clock.now(),
+            clock,
+            frames: 0,
+            max_catch_up: max_catch_up.max(1),
+        }
+    }
+
+    // sleeps until the next frame is due, then returns how many frames
+    // to run: one, or more if the host has fallen behind
+    pub fn wait(&mut self) -> u32 {
+        let next = self.due(self.frames + 1);
+        let now = self.clock.now();
+        if now < next {
+            self.clock.sleep(next - now);
+        }
+        let now = self.clock.now();
+        self.frames_due(now)
+    }
+
+    fn frames_due(&mut self, now: Duration) -> u32 {
+        let elapsed = now.saturating_sub(self.start);
+        let total = elapsed.as_nanos() * DELAY_TICK_FREQ as u128 / 1_000_000_000;
+        let n = (total as u64).saturating_sub(self.frames);
+
+        if n > self.max_catch_up as u64 {
+            // too far behind, start counting again from here
+            self.start = now;
+            self.frames = 0;
+            return self.max_catch_up
+        }
+        self.frames += n;
+        n as u32
+    }
+
+    // rounded up, so a clock that sleeps exactly as long as it's
+    // asked to wakes up with the frame due
+    fn due(&self, frame: u64) -> Duration {
+        let nanos = (frame * 1_000_000_000).div_ceil(DELAY_TICK_FREQ as u64);
+        self.start + Duration::from_nanos(nanos)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Scheduler<StdClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_nanos(1_000_000_000 / DELAY_TICK_FREQ as u64);
+
+    #[test]
+    fn one_frame_per_sixtieth() {
+        let mut s = Scheduler::new();
+        let t = s.start;
+        assert_eq!(s.frames_due(t + FRAME / 2), 0);
+        assert_eq!(s.frames_due(t + FRAME * 3 / 2), 1);
+        assert_eq!(s.frames_due(t + FRAME * 3 / 2), 0);
+
+        // a whole second is exactly 60 frames, with no rounding drift
+        let mut total = 1;
+        for k in 2..=60 {
+            total += s.frames_due(t + FRAME * k + Duration::from_nanos(k as u64));
+        }
+        assert_eq!(total, 60);
+        assert_eq!(s.due(60), t + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bounded_catch_up() {
+        let mut s = Scheduler::with_max_catch_up(3);
+        let t = s.start;
+        assert_eq!(s.frames_due(t + FRAME * 2 + FRAME / 2), 2);
+
+        // a long stall only runs a few frames, then carries on from there
+        let later = t + Duration::from_secs(5);
+        assert_eq!(s.frames_due(later), 3);
+        assert_eq!(s.frames_due(later + FRAME / 2), 0);
+        assert_eq!(s.frames_due(later + FRAME + FRAME / 2), 1);
+    }
+}
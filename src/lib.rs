@@ -0,0 +1,33 @@
+// a CHIP-8, SUPER-CHIP and XO-CHIP interpreter, with the pieces to
+// build a front-end around it: the VM runs programs and talks to the
+// host only through the driver traits in a Context, so it can be
+// embedded with displays, keyboards and sounds of the host's own.
+// Without the std feature, only the core is built, with no_std
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod instructions;
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod interpreter;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(test)]
+mod golden;
+
+// what most hosts need, in one place
+pub use instructions::{Instruction, Register};
+pub use interpreter::{
+    VM,
+    PROGRAM_START,
+    RAM_SIZE,
+    error::VmError,
+    quirks::{Quirks, LoadStore},
+    power_on::PowerOnPattern,
+    framebuffer::Framebuffer,
+    scheduler::{Scheduler, Clock},
+    drivers::{Context, Display, Input, Sound, Random},
+    drivers::input::{Key, KeySet},
+};
@@ -1,32 +1,190 @@
-mod instructions;
-mod interpreter;
-mod parser;
-mod rand;
+mod cli;
 
-use std::io::{self, Read, BufReader};
-use interpreter::{
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process;
+use std::io::{self, Read, BufReader, BufWriter};
+use cli::{Options, Screen};
+use chip8::debugger::{Debugger, DebugInput};
+use chip8::instructions::Instruction;
+use chip8::interpreter::{
     VM,
-    drivers::Context,
-    drivers::display::TerminalDisplay,
+    DELAY_TICK_FREQ,
+    savestate::SaveStateError,
+    scheduler::Scheduler,
+    drivers::{Context, Display},
+    drivers::display::{self, TerminalDisplay},
+    drivers::display::render::RenderMode,
+    drivers::display::palette::Palette,
+    drivers::display::probe::Support,
+    drivers::display::sixel::SixelDisplay,
+    drivers::display::kitty::KittyDisplay,
+    drivers::input::{TerminalInput, Hotkey, DEFAULT_HOLD_TIME},
+    drivers::random::Xorshift,
+    drivers::sound::{self, Sound},
+    drivers::sound::wav::WavSound,
 };
 
+const STATE_FILE: &str = "chip8.state";
+
+fn open_display(screen: Screen, scale: u8, palette: Option<Palette>, fade: u8) -> Box<dyn Display> {
+    let support = match screen {
+        Screen::Auto => display::detect(),
+        Screen::Sixel => Support::Sixel,
+        Screen::Kitty => Support::Kitty,
+        Screen::Cells(_) => Support::Cells,
+    };
+
+    match support {
+        Support::Sixel => {
+            let mut disp = SixelDisplay::new(scale, palette.unwrap_or_default());
+            disp.set_fade(fade);
+            Box::new(disp)
+        },
+        Support::Kitty => {
+            let mut disp = KittyDisplay::new(scale, palette.unwrap_or_default());
+            disp.set_fade(fade);
+            Box::new(disp)
+        },
+        Support::Cells => {
+            let mode = match screen {
+                Screen::Cells(mode) => mode,
+                _ => RenderMode::default(),
+            };
+            let mut disp = TerminalDisplay::with_mode(mode);
+            if let Some(palette) = palette {
+                disp.set_palette(palette);
+            }
+            disp.set_fade(fade);
+            Box::new(disp)
+        },
+    }
+}
+
+// the ROM file, or standard input for none or -
+fn read_rom(path: Option<&Path>) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    match path {
+        Some(path) if path != Path::new("-") => {
+            File::open(path)
+                .and_then(|mut f| f.read_to_end(&mut data))
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        },
+        _ => {
+            io::stdin().lock().read_to_end(&mut data)
+                .map_err(|e| format!("failed to read the ROM from standard input: {}", e))?;
+        },
+    }
+    Ok(data)
+}
+
 fn main() {
-    let data = {
-        let stdin = io::stdin();
-        let stdin_handle = stdin.lock();
-        let mut handle = BufReader::new(stdin_handle);
+    let opts = match cli::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("chip8: {}\nTry 'chip8 --help' for the options.", e);
+            process::exit(2)
+        },
+    };
+    if opts.help {
+        print!("{}", cli::USAGE);
+        return
+    }
 
-        let mut data = Vec::new();
-        handle.read_to_end(&mut data)
-            .expect("failed to read stdin data");
+    if let Err(e) = run(opts) {
+        eprintln!("chip8: {}", e);
+        process::exit(1)
+    }
+}
 
-        data
+fn run(opts: Options) -> Result<(), Box<dyn Error>> {
+    let data = read_rom(opts.rom.as_deref())?;
+    let mut vm = VM::with_power_on(opts.quirks, opts.power_on);
+    if let Some(ips) = opts.ips {
+        vm.set_cycles_per_frame(ips.saturating_add(DELAY_TICK_FREQ / 2) / DELAY_TICK_FREQ);
+    }
+    vm.load_at(opts.load_address, data)?;
+
+    let random = opts.seed.map_or_else(Xorshift::from_clock, Xorshift::new);
+    // the beeps can be recorded to a WAV file
+    let sound: Box<dyn Sound> = match &opts.wav {
+        Some(path) => Box::new(WavSound::create(path, sound::wav::DEFAULT_FREQUENCY, sound::wav::DEFAULT_VOLUME)
+            .map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => Box::new(()),
     };
 
-    let disp = TerminalDisplay::new();
-    let mut ctx = Context::new(disp, (), ());
-    let mut vm = VM::new();
+    if opts.headless {
+        let mut ctx = Context::with_random((), (), sound, random);
+        return run_headless(&mut vm, &mut ctx, &opts)
+    }
+
+    // the ROM may come in through stdin, so commands are read from the terminal
+    if opts.debug {
+        let tty = termion::get_tty()
+            .map_err(|e| format!("failed to open the terminal for commands: {}", e))?;
+        let mut ctx = Context::with_random((), DebugInput::default(), sound, random);
+        Debugger::new(BufReader::new(tty), io::stdout())
+            .run(&mut vm, &mut ctx)?;
+        return Ok(())
+    }
+
+    let disp = open_display(opts.screen, opts.scale, opts.palette, opts.fade);
+    let input = TerminalInput::with_keymap(opts.keymap, DEFAULT_HOLD_TIME)
+        .map_err(|e| format!("failed to open the terminal for input: {}", e))?;
+    let mut ctx = Context::with_random(disp, input, sound, random);
+
+    let mut scheduler = Scheduler::new();
+    let mut result = Ok(());
+    while !vm.halted() && result.is_ok() {
+        for _ in 0..scheduler.wait() {
+            result = result.and_then(|_| vm.run_frame(&mut ctx));
+        }
+        ctx.flush();
+
+        let result = match ctx.input_mut().take_hotkey() {
+            Some(Hotkey::SaveState) => File::create(STATE_FILE)
+                .map_err(SaveStateError::from)
+                .and_then(|f| vm.save_state(BufWriter::new(f))),
+            Some(Hotkey::LoadState) => File::open(STATE_FILE)
+                .map_err(SaveStateError::from)
+                .and_then(|f| vm.load_state(&mut ctx, BufReader::new(f))),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            eprint!("{}: {}\r\n", STATE_FILE, e);
+        }
+    }
+
+    // leave raw mode before reporting the fault
+    drop(ctx);
+    Ok(result?)
+}
+
+// runs as fast as it can with nothing on screen and no keys held; with
+// no one to press one, a program waiting for a key ends the run early
+fn run_headless<S: Sound>(vm: &mut VM, ctx: &mut Context<(), (), S>, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let cycles = opts.cycles.unwrap_or(0);
+    let mut ran = 0;
+    let mut waiting = false;
+    let result = vm.run_until(ctx, |vm| {
+        waiting = matches!(vm.current_instruction(), Instruction::LDK(_));
+        if waiting || ran == cycles {
+            return true
+        }
+        ran += 1;
+        false
+    });
 
-    vm.load(data);
-    vm.run(&mut ctx);
+    // the screen is written even after a fault, to see what led to it
+    if let Some(path) = &opts.dump_frame {
+        fs::write(path, vm.framebuffer().to_pbm())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    result?;
+    if waiting {
+        eprintln!("chip8: stopped after {} cycles, waiting for a key at 0x{:03X}", ran, vm.pc());
+    }
+    Ok(())
 }
@@ -1,13 +1,25 @@
 use crate::instructions::Instruction;
 
-pub fn read(buf: [u8; 2]) -> Instruction {
+// decodes the instruction at the start of `buf`, which must hold
+// at least two bytes; F000 NNNN also needs the two following it
+pub fn read(buf: &[u8]) -> Instruction {
     let i = u16::from_be_bytes([buf[0], buf[1]]);
     match i {
+        0xf000 => return match buf.get(2..4) {
+            Some(nnnn) => Instruction::LDLONG(u16::from_be_bytes([nnnn[0], nnnn[1]])),
+            None => Instruction::UNKNOWN(i),
+        },
         0x00e0 => return Instruction::CLS,
         0x00ee => return Instruction::RET,
+        0x00fb => return Instruction::SCR,
+        0x00fc => return Instruction::SCL,
+        0x00fd => return Instruction::EXIT,
+        0x00fe => return Instruction::LOW,
+        0x00ff => return Instruction::HIGH,
         _ => (),
     };
     match i & 0xf000 {
+        0x0000 => parse_0(i),
         0x1000 => parse_1(i),
         0x2000 => parse_2(i),
         0x3000 => parse_3(i),
@@ -27,6 +39,7 @@ pub fn read(buf: [u8; 2]) -> Instruction {
     }.unwrap_or(Instruction::UNKNOWN(i))
 }
 
+#[cfg(feature = "std")]
 pub fn read_all<T: AsRef<[u8]>>(data: T) -> Option<Vec<Instruction>> {
     let buf = data.as_ref();
     let n = buf.len();
@@ -36,9 +49,12 @@ pub fn read_all<T: AsRef<[u8]>>(data: T) -> Option<Vec<Instruction>> {
     }
 
     let mut inst = Vec::new();
+    let mut k = 0;
 
-    for k in (0..n).step_by(2) {
-        inst.push(read([buf[k], buf[k+1]]))
+    while k < n {
+        let i = read(&buf[k..]);
+        k += i.size() as usize;
+        inst.push(i);
     }
 
     Some(inst)
@@ -49,6 +65,14 @@ fn to8(i: u16) -> u8 {
     i as u8
 }
 
+fn parse_0(i: u16) -> Option<Instruction> {
+    match i & 0xfff0 {
+        0x00c0 => Some(Instruction::SCD(to8(i & 0x000f))),
+        0x00d0 => Some(Instruction::SCU(to8(i & 0x000f))),
+        _ => None,
+    }
+}
+
 fn parse_1(i: u16) -> Option<Instruction> {
     Some(Instruction::JPA(i & 0x0fff))
 }
@@ -66,10 +90,12 @@ fn parse_4(i: u16) -> Option<Instruction> {
 }
 
 fn parse_5(i: u16) -> Option<Instruction> {
-    if i&0x000f != 0 {
-        return None
+    match i & 0x000f {
+        0x0000 => Some(Instruction::SER(to8((i & 0x0f00) >> 8), to8((i & 0x00f0) >> 4))),
+        0x0002 => Some(Instruction::LDRNGST(to8((i & 0x0f00) >> 8), to8((i & 0x00f0) >> 4))),
+        0x0003 => Some(Instruction::LDRNGRD(to8((i & 0x0f00) >> 8), to8((i & 0x00f0) >> 4))),
+        _ => None,
     }
-    Some(Instruction::SER(to8((i & 0x0f00) >> 8), to8((i & 0x00f0) >> 4)))
 }
 
 fn parse_6(i: u16) -> Option<Instruction> {
@@ -137,6 +163,12 @@ fn parse_f(i: u16) -> Option<Instruction> {
         0x0033 => Some(Instruction::LDBCD(to8((i & 0x0f00) >> 8))),
         0x0055 => Some(Instruction::LDREGST(to8((i & 0x0f00) >> 8))),
         0x0065 => Some(Instruction::LDREGRD(to8((i & 0x0f00) >> 8))),
+        0x0030 => Some(Instruction::LDHDIG(to8((i & 0x0f00) >> 8))),
+        0x0075 => Some(Instruction::LDRPLST(to8((i & 0x0f00) >> 8))),
+        0x0085 => Some(Instruction::LDRPLRD(to8((i & 0x0f00) >> 8))),
+        0x0001 => Some(Instruction::PLANE(to8((i & 0x0f00) >> 8))),
+        0x0002 if i == 0xf002 => Some(Instruction::AUDIO),
+        0x003a => Some(Instruction::PITCH(to8((i & 0x0f00) >> 8))),
         _ => None,
     }
 }
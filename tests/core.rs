@@ -0,0 +1,137 @@
+// the core as firmware would use it, with the host supplying the
+// drivers, the clock and the random numbers; run it with
+//
+//   cargo test --no-default-features --test core
+//
+// to check the VM and the decoder build without std, and run
+// without allocating
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use chip8::{
+    VM, Context, Display, Input, Sound, Random, Clock, Scheduler,
+    Framebuffer, KeySet, Quirks, Instruction, parser,
+};
+
+// counts the allocations made on this thread while asked to
+struct Counting;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(|c| c.get()) {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATIONS.with(|n| n.set(0));
+    COUNTING.with(|c| c.set(true));
+    let result = f();
+    COUNTING.with(|c| c.set(false));
+    (result, ALLOCATIONS.with(|n| n.get()))
+}
+
+// a timer that only moves when waited on
+struct Ticks(Duration);
+
+impl Clock for Ticks {
+    fn now(&mut self) -> Duration {
+        self.0
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.0 += duration;
+    }
+}
+
+// counts frames and lit pixels, like a small LCD would show them
+#[derive(Default)]
+struct Lcd {
+    frames: u32,
+    lit: usize,
+}
+
+impl Display for Lcd {
+    fn present(&mut self, fb: &Framebuffer) {
+        self.frames += 1;
+        self.lit = fb.rows().flatten().filter(|&&px| px != 0).count();
+    }
+}
+
+// a keypad with nothing pressed
+struct Keypad;
+
+impl Input for Keypad {
+    fn poll_keyboard(&mut self) -> KeySet {
+        KeySet::from([false; 16])
+    }
+}
+
+struct Buzzer(bool);
+
+impl Sound for Buzzer {
+    fn beep_start(&mut self) {
+        self.0 = true;
+    }
+
+    fn beep_end(&mut self) {
+        self.0 = false;
+    }
+}
+
+// a board's noise source, here just counting
+struct Noise(u8);
+
+impl Random for Noise {
+    fn byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(97);
+        self.0
+    }
+}
+
+#[test]
+fn runs_a_second_without_allocating() {
+    let mut vm = VM::new(Quirks::VIP);
+    vm.load(include_bytes!("../roms/maze.rom")).unwrap();
+    let mut ctx = Context::with_random(Lcd::default(), Keypad, Buzzer(false), Noise(0));
+
+    let (frames, n) = allocations(|| {
+        let mut scheduler = Scheduler::with_clock(Ticks(Duration::ZERO), 4);
+        let mut frames = 0;
+        while frames < 60 {
+            for _ in 0..scheduler.wait() {
+                vm.run_frame(&mut ctx).unwrap();
+                frames += 1;
+            }
+        }
+        frames
+    });
+    assert_eq!(n, 0);
+    assert_eq!(frames, 60);
+
+    let lcd = ctx.display_mut();
+    assert!(lcd.frames > 0);
+    assert!(lcd.lit > 0);
+}
+
+#[test]
+fn decodes_without_allocating() {
+    let (inst, n) = allocations(|| parser::read(&[0xd0, 0x15]));
+    assert_eq!(n, 0);
+    assert_eq!(inst, Instruction::DRW(0, 1, 5));
+}